@@ -0,0 +1,105 @@
+use std::fs;
+use std::time::{Duration, Instant};
+use crate::{two, five, seven, nine};
+use crate::intcode::{load_program, BufferIo, IntcodeMachine};
+
+// name, input directory, solver
+type Workload = (&'static str, &'static str, fn(&[u8]));
+
+// The intcode heavy puzzles
+const WORKLOADS: [Workload; 5] = [
+    ("2b", "2", |input| { two::two_b(input); }),
+    ("5b", "5", |input| { five::five_b(input); }),
+    ("7a", "7", |input| { seven::seven_a(input); }),
+    ("7b", "7", |input| { seven::seven_b(input); }),
+    ("9b", "9", |input| { nine::nine_b(input); }),
+];
+
+// name, input directory, cells patched before running, input
+type Program = (&'static str, &'static str, &'static [(usize, i64)], &'static [i64]);
+
+// Single runs of puzzle programs, for comparing the interpreter with its
+// decode cache against the same interpreter decoding as it used to, with
+// allocations and no cache, and against the compiled backend
+const PROGRAMS: [Program; 4] = [
+    ("2", "2", &[(1, 12), (2, 2)], &[]),
+    ("5", "5", &[], &[5]),
+    ("7", "7", &[], &[3, 0]),
+    ("9", "9", &[], &[2]),
+];
+
+// How a machine is set up before it is timed
+type Backend = fn(&mut IntcodeMachine<BufferIo>);
+
+const BACKENDS: [Backend; 3] = [
+    |mach| mach.baseline(),
+    |mach| mach.interpret(),
+    |mach| mach.compile(),
+];
+
+// Times each intcode workload over `rounds` runs, which must be at least one,
+// reading puzzle inputs from the numbered directories under `root`, and
+// prints the best and mean times.
+pub fn bench(root: &str, rounds: u32) {
+    println!("{:<4} {:>12} {:>12}", "", "best", "mean");
+    for (name, dir, solve) in WORKLOADS.iter() {
+        let path = format!("{}/{}/input", root, dir);
+        let input = match fs::read(&path) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                continue;
+            },
+        };
+        let mut best = Duration::from_secs(u64::MAX);
+        let mut total = Duration::from_secs(0);
+        for _ in 0..rounds {
+            let start = Instant::now();
+            solve(&input);
+            let took = start.elapsed();
+            best = best.min(took);
+            total += took;
+        }
+        println!("{:<4} {:>10.3}ms {:>10.3}ms", name,
+                 best.as_secs_f64() * 1e3,
+                 total.as_secs_f64() * 1e3 / rounds as f64);
+    }
+    backends(root, rounds);
+}
+
+// Times each of PROGRAMS interpreted with the allocating decoder, with the
+// decode cache, and compiled, and how many times faster than the allocating
+// decoder the other two are.  Compiling is part of the time, as it would be
+// for any caller.
+fn backends(root: &str, rounds: u32) {
+    println!();
+    println!("{:<4} {:>12} {:>12} {:>12} {:>8} {:>8}",
+             "", "allocating", "cached", "compiled", "speedup", "");
+    for (name, dir, patch, input) in PROGRAMS.iter() {
+        let path = format!("{}/{}/input", root, dir);
+        let mut program = match load_program(&path) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            },
+        };
+        for (addr, value) in patch.iter() {
+            program[*addr] = *value;
+        }
+        let time = |setup: &Backend| {
+            let mut best = Duration::from_secs(u64::MAX);
+            for _ in 0..rounds {
+                let start = Instant::now();
+                let mut mach = IntcodeMachine::with_io(program.to_owned(), BufferIo::new(input.to_vec()));
+                setup(&mut mach);
+                mach.run_program().unwrap();
+                best = best.min(start.elapsed());
+            }
+            best.as_secs_f64() * 1e3
+        };
+        let times: Vec<f64> = BACKENDS.iter().map(time).collect();
+        println!("{:<4} {:>10.3}ms {:>10.3}ms {:>10.3}ms {:>7.1}x {:>7.1}x", name,
+                 times[0], times[1], times[2], times[0] / times[1], times[0] / times[2]);
+    }
+}
@@ -9,7 +9,7 @@ use crate::intcode::IntcodeMachine;
 fn run_robot(program: Vec<i64>, start_color: i64) -> HashMap<Point,i64> {
     let (m_in, m_out, mut mach) = IntcodeMachine::new(program);
     thread::spawn(move || {
-        mach.run_program();
+        mach.run_program().unwrap();
     });
 
     let mut location = Point{x: 0, y: 0};
@@ -103,7 +103,7 @@ where
     I: BufRead,
 {
 
-    let p = intcode::read_program(buf);
+    let p = intcode::read_program(buf).unwrap();
     let panel = run_robot(p, 0);
     panel.len()
 }
@@ -114,7 +114,7 @@ where
     I: BufRead,
 {
 
-    let p = intcode::read_program(buf);
+    let p = intcode::read_program(buf).unwrap();
     let panel = run_robot(p, 1);
 
     // draw panel
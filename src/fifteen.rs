@@ -2,8 +2,7 @@ use std::fmt;
 use std::string::String;
 use std::io::BufRead;
 use std::collections::HashMap;
-use std::thread;
-use crate::intcode::{self, IntcodeMachine};
+use crate::intcode::{self, BufferIo, IntcodeError, IntcodeMachine};
 use crate::point::Point;
 
 #[derive(Debug)]
@@ -78,12 +77,12 @@ impl fmt::Display for Space {
 
 }
 
-fn explore_space<I>(buf: I) -> Space
+fn explore_space<I>(buf: I) -> Result<Space, IntcodeError>
 where
     I: BufRead
 {
-    let program = intcode::read_program(buf);
-    let (m_in, m_out, mut mach) = IntcodeMachine::new(program);
+    let program = intcode::read_program(buf)?;
+    let mut mach = IntcodeMachine::with_io(program, BufferIo::default());
     let bbox = BoundingBox{
         max_x: 0,
         min_x: 0,
@@ -105,22 +104,16 @@ where
     };
     space.points.insert(loc.to_owned(), ".".to_string());
 
-    thread::spawn(move || {
-        mach.run_program();
-    });
-
     loop {
         let next_loc = directions[dir].0(&loc);
         if next_loc.x == 0 && next_loc.y == 0 {
-            // The machine is blocking on an inp instruction
-            // We send an invalid input command so that the program halts
-            // rather than throwing a panic on recv err 
-            m_in.send(-1).unwrap();
+            // back at the start; the droid is simply left waiting for input
             break;
         }
-        m_in.send(directions[dir].1).unwrap();
+        mach.io_mut().input.push_back(directions[dir].1);
         space.bbox.expand(&next_loc);
-        match m_out.recv().unwrap() {
+        mach.run_until(None)?;
+        match mach.io_mut().output.pop_front().expect("droid sent no status") {
             0 => { // wall
                 // turn back to the right
                 space.points.insert(next_loc.to_owned(), "#".to_string());
@@ -141,7 +134,7 @@ where
         }
     }
     // print!("{}", space);
-    space
+    Ok(space)
 }
 
 fn visit(point: &Point, pdist:usize, distances: &mut HashMap<Point,usize>) {
@@ -166,15 +159,14 @@ fn next(distances: &HashMap<Point,usize>) -> Option<Point> {
 }
 
 fn dikstras(space: &Space, origin: Point, target: Option<Point>) -> HashMap<Point,usize> {
-    let mut distances: HashMap<Point,usize> = HashMap::from(
+    let mut distances: HashMap<Point,usize> =
         space.points.iter()
                     .filter(|(_, v)| *v != &"#")
                     .map(
                         |(k, _)| {
                             (k.to_owned(), usize::max_value())
                         }
-                    ).collect()
-    );
+                    ).collect();
     let mut visited = HashMap::new();
 
     let mut cur = origin;
@@ -208,7 +200,7 @@ pub fn fifteen_a<I>(buf: I) -> usize
 where
     I: BufRead
 {
-    let space = explore_space(buf);
+    let space = explore_space(buf).unwrap();
     let origin = Point{x:0,y:0};
     let distances = dikstras(&space, origin.clone(), space.target.clone());
     *distances.get(&space.target.unwrap()).unwrap()
@@ -218,7 +210,7 @@ pub fn fifteen_b<I>(buf: I) -> usize
 where
     I: BufRead
 {
-    let space = explore_space(buf);
+    let space = explore_space(buf).unwrap();
     let distances = dikstras(&space, space.target.clone().unwrap(), None);
     let mut by_dist: Vec<_> = distances.iter().collect();
     by_dist.sort_by(|a, b| b.1.cmp(a.1));
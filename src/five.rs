@@ -1,25 +1,20 @@
 use std::io::BufRead;
-use std::thread;
 use crate::intcode;
-use crate::intcode::IntcodeMachine;
+use crate::intcode::{BufferIo, IntcodeMachine};
+
+// runs the diagnostic program for the given system, returning the last output
+fn run_diagnostic(p: Vec<i64>, system_id: i64) -> i64 {
+    let mut mach = IntcodeMachine::with_io(p, BufferIo::new(vec![system_id]));
+    mach.run_program().unwrap();
+    *mach.io_mut().output.back().unwrap()
+}
 
 pub fn five_a<I>(buf: I) -> i64
 where
     I: BufRead,
 {
-    let p = intcode::read_program(buf);
-    let (mach_in, mach_out, mut mach) = IntcodeMachine::new(p);
-
-    mach_in.send(1).unwrap();
-    let _exit_code = thread::spawn(move || {
-        mach.run_program()
-    });
-
-    let mut out_vec = Vec::new();
-    for i in mach_out {
-        out_vec.push(i);
-    }
-    out_vec[out_vec.len()-1]
+    let p = intcode::read_program(buf).unwrap();
+    run_diagnostic(p, 1)
 }
 
 
@@ -27,16 +22,6 @@ pub fn five_b<I>(buf: I) -> i64
 where
     I: BufRead,
 {
-    let p = intcode::read_program(buf);
-    let (mach_in, mach_out, mut mach) = IntcodeMachine::new(p);
-    mach_in.send(5).unwrap();
-    let _exit_code = thread::spawn(move || {
-        mach.run_program()
-    });
-
-    let mut out_vec = Vec::new();
-    for i in mach_out {
-        out_vec.push(i);
-    }
-    out_vec[out_vec.len()-1]
+    let p = intcode::read_program(buf).unwrap();
+    run_diagnostic(p, 5)
 }
@@ -1,222 +1,525 @@
-use std::io::BufRead;
-use std::collections::VecDeque;
+use std::fmt;
+use std::error::Error;
 use std::sync::mpsc::{channel, Sender, Receiver};
 
-pub fn read_program<I>(mut buf: I) -> Vec<i64>
-where
-    I: BufRead,
-{
-    let mut line = String::new();
-    buf.read_line(&mut line).unwrap();
-    line.trim()
-        .split(",")
-        .into_iter()
-        .map(|x| x.parse().expect("error parsing number"))
-        .collect()
+mod io;
+mod memory;
+mod snapshot;
+mod disasm;
+mod asm;
+mod debugger;
+mod trace;
+mod cache;
+mod network;
+mod nic;
+mod ascii;
+mod access;
+mod arith;
+mod loader;
+mod search;
+mod symbolic;
+mod compiled;
+mod transpile;
+pub use self::io::{IntcodeIo, ChannelIo, BufferIo, FnIo, AsciiIo};
+pub use self::snapshot::Snapshot;
+pub use self::disasm::{disassemble, disassemble_map};
+pub use self::asm::assemble;
+pub use self::debugger::Debugger;
+pub use self::trace::{TraceLog, Profiler};
+pub use self::network::{Network, Outcome};
+pub use self::nic::{NatEvent, PacketNetwork};
+pub use self::ascii::{AsciiMachine, Response};
+pub use self::access::analyse;
+pub use self::arith::Arithmetic;
+pub use self::loader::{read_program, load_program, encode_program, Encoding};
+pub use self::search::{Run, Search, Slot};
+pub use self::symbolic::exit_value;
+pub use self::transpile::transpile;
+use self::trace::SharedSink;
+use self::memory::Memory;
+use self::cache::{decode_allocating, decode_word, Decoded, DecodeCache};
+use self::compiled::{Compiled, Effect};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    BadOpcode,
+    BadMode,
+    ValueModeWrite,
+    NegativeAddress(i64),
+    InputClosed,
+    OutputClosed,
+    // a result didn't fit, or a value too wide was used as an address or output
+    Overflow,
 }
 
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::BadOpcode => write!(f, "unknown opcode"),
+            Fault::BadMode => write!(f, "bad parameter mode"),
+            Fault::ValueModeWrite => write!(f, "write parameter in value mode"),
+            Fault::NegativeAddress(a) => write!(f, "negative address {}", a),
+            Fault::InputClosed => write!(f, "input closed"),
+            Fault::OutputClosed => write!(f, "output closed"),
+            Fault::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntcodeError {
+    // the machine faulted executing `instruction` at `ip`, with relative base `off`
+    Machine {
+        ip: usize,
+        instruction: i64,
+        off: i64,
+        fault: Fault,
+    },
+    // `token`, at the given (1-based) line and column, was not a number
+    Parse {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    // assembly source was rejected at the given (1-based) line
+    Asm {
+        line: usize,
+        message: String,
+    },
+    Io(String),
+}
 
-pub struct IntcodeMachine {
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeError::Machine { ip, instruction, off, fault } => {
+                write!(f, "{} at ip {} (instruction {}, relative base {})",
+                       fault, ip, instruction, off)
+            },
+            IntcodeError::Parse { line, column, token } => {
+                write!(f, "error parsing number {:?} at line {}, column {}", token, line, column)
+            },
+            IntcodeError::Asm { line, message } => write!(f, "line {}: {}", line, message),
+            IntcodeError::Io(e) => write!(f, "error reading program: {}", e),
+        }
+    }
+}
+
+impl Error for IntcodeError {}
+
+
+#[derive(Clone)]
+pub struct IntcodeMachine<T = ChannelIo> {
     ip: usize,
     off: i64,
-    out_tx: Sender<i64>,
-    in_rx: Receiver<i64>,
-    memory: Vec<i64>,
+    io: T,
+    memory: Memory,
+    arithmetic: Arithmetic,
+    // instructions already decoded, by address
+    cache: DecodeCache,
+    // decode every instruction as the old interpreter did, for benchmarks
+    baseline: bool,
+    // the program compiled ahead of time, once asked for
+    compiled: Option<Compiled>,
+    // where executed instructions are reported, when tracing
+    trace: Option<SharedSink>,
 }
 
-#[derive(Debug)]
-enum Mode {
+// Why a call to run_until returned control to the caller
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    Halted,
+    NeedsInput,
+    Output(i64),
+    StepLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
     Pointer,
     Value,
     Relative,
 }
 
 impl IntcodeMachine {
-    pub fn new(mut program: Vec<i64>) -> (Sender<i64>, Receiver<i64>, IntcodeMachine) {
+    pub fn new(program: Vec<i64>) -> (Sender<i64>, Receiver<i64>, IntcodeMachine) {
         let (itx, irx) = channel::<i64>();
         let (otx, orx) = channel::<i64>();
-        let addl_mem = vec![0i64; 0xffff - program.len()];
-        program.extend(addl_mem);
-        let mach = IntcodeMachine {
+        let mach = IntcodeMachine::with_io(program, ChannelIo {
+            tx: otx,
+            rx: irx,
+        });
+        (itx, orx, mach)
+    }
+}
+
+impl<T: IntcodeIo> IntcodeMachine<T> {
+    pub fn with_io(program: Vec<i64>, io: T) -> IntcodeMachine<T> {
+        IntcodeMachine::with_arithmetic(program, io, Arithmetic::default())
+    }
+
+    pub fn with_arithmetic(program: Vec<i64>, io: T, arithmetic: Arithmetic) -> IntcodeMachine<T> {
+        let mut mach = IntcodeMachine {
             ip: 0,
             off: 0,
-            in_rx: irx,
-            out_tx: otx,
-            memory: program,
+            io,
+            memory: Memory::new(program),
+            arithmetic,
+            cache: DecodeCache::default(),
+            baseline: false,
+            compiled: None,
+            trace: None,
         };
-        (itx, orx, mach)
+        if cfg!(feature = "compiled") {
+            mach.compile();
+        }
+        mach
+    }
+
+    // Compiles the program as it stands, so that from now on run_program and
+    // run_until execute arithmetic, comparisons and jumps without decoding
+    // them.  Anything else, and any instruction which gets written over, is
+    // still interpreted, and the machine behaves exactly as it would without.
+    pub fn compile(&mut self) {
+        let mut compiled = Compiled::new(self.memory.dense());
+        for (addr, _) in self.memory.wide() {
+            compiled.invalidate(addr);
+        }
+        self.compiled = Some(compiled);
+    }
+
+    // Forgets any compiled code, so that every instruction is interpreted.
+    pub fn interpret(&mut self) {
+        self.compiled = None;
+    }
+
+    // Interprets every instruction, decoding it afresh and allocating as the
+    // interpreter used to, so that benchmarks have something to compare the
+    // cache against.  Everything else is as the interpreter does it now.
+    pub fn baseline(&mut self) {
+        self.baseline = true;
+        self.interpret();
+    }
+
+    pub fn io(&self) -> &T {
+        &self.io
     }
 
-    pub fn run_program(&mut self) -> i64 {
-        self.ip = 0;
+    pub fn io_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.off
+    }
+
+    pub fn peek(&self, addr: usize) -> i64 {
+        self.memory.get(addr)
+    }
+
+    pub fn poke(&mut self, addr: usize, value: i64) {
+        self.write(addr, value);
+    }
+
+    // Runs until the program halts, waiting on the input whenever the program
+    // asks for some.
+    pub fn run_program(&mut self) -> Result<i64, IntcodeError> {
         loop {
-            let (mode, opcode) = self.parse_instruction();
-            match opcode {
-                1 => self.add(mode),
-                2 => self.mul(mode),
-                3 => self.inp(mode),
-                4 => self.out(mode),
-                5 => self.jit(mode),
-                6 => self.jif(mode),
-                7 => self.lt(mode),
-                8 => self.eq(mode),
-                9 => self.rbo(mode),
-                99 => break,
-                _ => panic!("bad input"),
+            self.run_compiled(usize::MAX)?;
+            if self.exec(true)? == Some(StopReason::Halted) {
+                break;
             }
         }
-        self.memory[0] // exit code
+        Ok(self.memory.get(0)) // exit code
+    }
+
+    // Executes exactly one instruction.  Returns None if the machine can keep
+    // going, otherwise the reason it stopped.  An `in` with no queued input
+    // does not execute, so stepping again retries it.
+    pub fn step(&mut self) -> Result<Option<StopReason>, IntcodeError> {
+        self.exec(false)
     }
 
-    fn parse_instruction(&mut self) -> (Vec<Mode>, u8) {
-        let mut mode = Vec::<Mode>::new();
-        let mut tmp = self.memory[self.ip];
-        let opcode = (tmp % 100) as u8;
-        tmp = tmp / 100;
+    // Steps until something needs the caller's attention, or until `limit`
+    // instructions have executed.
+    pub fn run_until(&mut self, limit: Option<usize>) -> Result<StopReason, IntcodeError> {
+        self.run_within(&mut limit.unwrap_or(usize::MAX))
+    }
+
+    // As run_until, but takes every instruction executed out of `budget`, so
+    // that one limit can cover a run which stops for output many times.
+    pub fn run_within(&mut self, budget: &mut usize) -> Result<StopReason, IntcodeError> {
+        loop {
+            if *budget == 0 {
+                return Ok(StopReason::StepLimit);
+            }
+            *budget -= self.run_compiled(*budget)?;
+            if *budget == 0 {
+                return Ok(StopReason::StepLimit);
+            }
+            match self.step()? {
+                None => *budget -= 1,
+                Some(StopReason::NeedsInput) => return Ok(StopReason::NeedsInput),
+                Some(reason) => {
+                    *budget -= 1;
+                    return Ok(reason);
+                },
+            }
+        }
+    }
 
-        while tmp > 0 {
-            match tmp % 10 {
-                0 => mode.push(Mode::Pointer),
-                1 => mode.push(Mode::Value),
-                2 => mode.push(Mode::Relative),
-                _ => panic!("bad mode"),
+    // Runs compiled instructions from ip until one isn't compiled, `budget`
+    // of them have run, or one writes over code.  Returns how many ran.
+    // Tracing sees every instruction, so nothing runs compiled while it's on.
+    fn run_compiled(&mut self, budget: usize) -> Result<usize, IntcodeError> {
+        let mut compiled = match self.compiled.take() {
+            Some(compiled) if self.trace.is_none() => compiled,
+            other => {
+                self.compiled = other;
+                return Ok(0);
+            },
+        };
+        let mut steps = 0;
+        let mut res = Ok(());
+        while steps < budget {
+            let (op, len) = match compiled.get(self.ip) {
+                Some(op) => op,
+                None => break,
+            };
+            match op(&self.memory, &mut self.off, self.arithmetic) {
+                Ok(Effect::Next) => self.ip += len,
+                Ok(Effect::Jump(to)) => self.ip = to,
+                Ok(Effect::Write(addr, value)) => {
+                    self.ip += len;
+                    self.memory.set_wide(addr, value);
+                    self.cache.invalidate(addr);
+                    if compiled.invalidate(addr) {
+                        steps += 1;
+                        break;
+                    }
+                },
+                Err(fault) => {
+                    res = Err(self.error(fault));
+                    break;
+                },
             }
-            tmp = tmp / 10;
+            steps += 1;
+        }
+        self.compiled = Some(compiled);
+        res.map(|_| steps)
+    }
+
+    fn exec(&mut self, wait: bool) -> Result<Option<StopReason>, IntcodeError> {
+        let event = match self.trace {
+            Some(_) => self.begin_trace(),
+            None => None,
+        };
+        let res = match self.fetch() {
+            Ok(ins) => match ins.opcode {
+                1 => self.add(&ins),
+                2 => self.mul(&ins),
+                3 => self.inp(&ins, wait),
+                4 => self.out(&ins),
+                5 => self.jit(&ins),
+                6 => self.jif(&ins),
+                7 => self.lt(&ins),
+                8 => self.eq(&ins),
+                9 => self.rbo(&ins),
+                99 => Ok(Some(StopReason::Halted)),
+                _ => Err(Fault::BadOpcode),
+            },
+            Err(fault) => Err(fault),
+        };
+        match (event, &res) {
+            (Some(_), Ok(Some(StopReason::NeedsInput))) => {},
+            (Some(event), Ok(_)) => self.end_trace(event),
+            _ => {},
+        }
+        res.map_err(|fault| self.error(fault))
+    }
+
+    // wraps a fault with the state of the machine at the faulting instruction
+    fn error(&self, fault: Fault) -> IntcodeError {
+        IntcodeError::Machine {
+            ip: self.ip,
+            instruction: self.memory.get(self.ip),
+            off: self.off,
+            fault,
+        }
+    }
+
+    // Decodes the instruction at ip, from the cache if it is there.  Only
+    // the program proper is cached, so that stray jumps into far memory
+    // can't grow the cache without bound.
+    fn fetch(&mut self) -> Result<Decoded, Fault> {
+        if self.baseline {
+            return decode_allocating(&self.memory, self.ip);
         }
-        while mode.len() < 3 {
-            mode.push(Mode::Pointer);
+        if let Some(ins) = self.cache.get(self.ip) {
+            return Ok(ins);
         }
+        let (opcode, modes) = decode_word(self.memory.get(self.ip))?;
+        let ins = Decoded {
+            opcode,
+            modes,
+            args: [
+                self.memory.get(self.ip + 1),
+                self.memory.get(self.ip + 2),
+                self.memory.get(self.ip + 3),
+            ],
+        };
+        if self.ip < self.memory.dense().len() {
+            self.cache.insert(self.ip, ins);
+        }
+        Ok(ins)
+    }
 
-        mode.reverse();
-        (mode, opcode)
+    // every write to memory goes through here, to keep the cache honest
+    fn write(&mut self, addr: usize, value: i64) {
+        self.memory.set(addr, value);
+        self.invalidate(addr);
+    }
+
+    fn write_wide(&mut self, addr: usize, value: i128) {
+        self.memory.set_wide(addr, value);
+        self.invalidate(addr);
+    }
+
+    fn invalidate(&mut self, addr: usize) {
+        self.cache.invalidate(addr);
+        if let Some(compiled) = &mut self.compiled {
+            compiled.invalidate(addr);
+        }
     }
 
     // Instruction implementations:
-    fn add(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        let sum = params[0] + params[1];
-        let res_addr = self.eval_write_param(mode.pop().unwrap(), self.ip + 3);
-        self.memory[res_addr] = sum;
+    fn add(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        let sum = self.arithmetic.add(self.wide_param(ins, 0)?, self.wide_param(ins, 1)?)?;
+        let res_addr = self.write_param(ins, 2)?;
+        self.write_wide(res_addr, sum);
         self.ip += 4;
+        Ok(None)
     }
 
-    fn mul(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        let prod = params[0] * params[1];
-        let res_addr = self.eval_write_param(mode.pop().unwrap(), self.ip + 3);
-        self.memory[res_addr] = prod;
+    fn mul(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        let prod = self.arithmetic.mul(self.wide_param(ins, 0)?, self.wide_param(ins, 1)?)?;
+        let res_addr = self.write_param(ins, 2)?;
+        self.write_wide(res_addr, prod);
         self.ip += 4;
+        Ok(None)
     }
 
-    fn inp(&mut self, mut mode: Vec<Mode>) {
-        let res_addr = self.eval_write_param(mode.pop().unwrap(), self.ip + 1);
-        let inp = self.read_input();
-        self.memory[res_addr] = inp;
+    fn inp(&mut self, ins: &Decoded, wait: bool) -> Result<Option<StopReason>, Fault> {
+        let res_addr = self.write_param(ins, 0)?;
+        let inp = if wait {
+            self.io.wait_input()?
+        } else {
+            match self.io.read_input()? {
+                Some(inp) => inp,
+                None => return Ok(Some(StopReason::NeedsInput)),
+            }
+        };
+        self.write(res_addr, inp);
         self.ip += 2;
+        Ok(None)
     }
 
-    fn out(&mut self, mut mode: Vec<Mode>) {
-        let out = self.eval_params(&mut mode, 1);
-        self.write_output(out[0]);
+    fn out(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        let out = self.param(ins, 0)?;
+        self.io.write_output(out)?;
         self.ip += 2;
+        Ok(Some(StopReason::Output(out)))
     }
 
 
-    fn jit(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        if params[0] != 0 {
-            self.ip = self.addr(params[1]);
+    fn jit(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        if self.param(ins, 0)? != 0 {
+            self.ip = self.addr(self.param(ins, 1)?)?;
         } else {
             self.ip += 3;
         }
-
+        Ok(None)
     }
 
-    fn jif(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        if params[0] == 0 {
-            self.ip = self.addr(params[1]);
+    fn jif(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        if self.param(ins, 0)? == 0 {
+            self.ip = self.addr(self.param(ins, 1)?)?;
         } else {
             self.ip += 3;
         }
+        Ok(None)
     }
 
-    fn lt(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        let res_addr = self.eval_write_param(mode.pop().unwrap(), self.ip + 3);
-        if params[0] < params[1] {
-            self.memory[res_addr] = 1;
-        } else {
-            self.memory[res_addr] = 0;
-        }
+    fn lt(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        let less = self.wide_param(ins, 0)? < self.wide_param(ins, 1)?;
+        let res_addr = self.write_param(ins, 2)?;
+        self.write(res_addr, less as i64);
         self.ip += 4;
+        Ok(None)
     }
 
-    fn eq(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 2);
-        let res_addr = self.eval_write_param(mode.pop().unwrap(), self.ip + 3);
-        if params[0] == params[1] {
-            self.memory[res_addr] = 1;
-        } else {
-            self.memory[res_addr] = 0;
-        }
+    fn eq(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        let equal = self.wide_param(ins, 0)? == self.wide_param(ins, 1)?;
+        let res_addr = self.write_param(ins, 2)?;
+        self.write(res_addr, equal as i64);
         self.ip += 4;
+        Ok(None)
     }
 
-    fn rbo(&mut self, mut mode: Vec<Mode>) {
-        let params = self.eval_params(&mut mode, 1);
-        self.off += params[0];
+    fn rbo(&mut self, ins: &Decoded) -> Result<Option<StopReason>, Fault> {
+        self.off = self.off.checked_add(self.param(ins, 0)?).ok_or(Fault::Overflow)?;
         self.ip += 2;
+        Ok(None)
     }
 
-    // Internal helpers 
-    fn deref(&mut self, addr: usize, off: i64) -> i64 {
-        let ptr = self.memory[addr];
-        self.memory[(ptr + off) as usize]
-    }
-
-    fn addr(&mut self, value: i64) -> usize {
-        value as usize
+    // Internal helpers
+    fn addr(&self, value: i64) -> Result<usize, Fault> {
+        if value < 0 {
+            return Err(Fault::NegativeAddress(value));
+        }
+        Ok(value as usize)
     }
 
-    fn read_input(&mut self) -> i64 {
-        self.in_rx.recv().unwrap()
+    // the address a relative mode argument refers to
+    fn relative(&self, arg: i64) -> Result<usize, Fault> {
+        self.addr(arg.checked_add(self.off).ok_or(Fault::Overflow)?)
     }
 
-    fn write_output(&mut self, out: i64) {
-        self.out_tx.send(out).expect("unable to send output");
+    // the value of the `n`th parameter, which must fit in 64 bits
+    fn param(&self, ins: &Decoded, n: usize) -> Result<i64, Fault> {
+        let arg = ins.args[n];
+        let addr = match ins.modes[n] {
+            Mode::Value => return Ok(arg),
+            Mode::Pointer => self.addr(arg)?,
+            Mode::Relative => self.relative(arg)?,
+        };
+        if self.memory.is_wide(addr) {
+            return Err(Fault::Overflow);
+        }
+        Ok(self.memory.get(addr))
     }
 
-    // returns a Vec of n values
-    fn eval_params(&mut self, mode: &mut Vec<Mode>, n: usize) -> VecDeque<i64> {
-        let mut ret = VecDeque::new();
-        let mut i = 0;
-        while n > i {
-            let base = self.ip + i + 1;
-            match mode.pop() {
-                Some(Mode::Value) => {
-                    ret.push_back(self.memory[base]);
-                },
-                Some(Mode::Pointer) => {
-                    ret.push_back(self.deref(base, 0));
-                },
-                Some(Mode::Relative) => {
-                    ret.push_back(self.deref(base, self.off));
-                },
-                None => panic!("mode too short"),
-            }
-            i += 1;
+    // the value of the `n`th parameter, for arithmetic and comparisons
+    fn wide_param(&self, ins: &Decoded, n: usize) -> Result<i128, Fault> {
+        let arg = ins.args[n];
+        match ins.modes[n] {
+            Mode::Value => Ok(arg as i128),
+            Mode::Pointer => Ok(self.memory.get_wide(self.addr(arg)?)),
+            Mode::Relative => Ok(self.memory.get_wide(self.relative(arg)?)),
         }
-        ret
     }
 
-    fn eval_write_param(&mut self, mode: Mode, addr: usize) -> usize {
-        match mode {
-            Mode::Value => panic!("cannot calculate write param in value mode"),
-            Mode::Pointer => self.addr(self.memory[addr]),
-            Mode::Relative => {
-                self.addr(self.memory[addr] + self.off)
-            },
+    // the address the `n`th parameter writes to
+    fn write_param(&self, ins: &Decoded, n: usize) -> Result<usize, Fault> {
+        let arg = ins.args[n];
+        match ins.modes[n] {
+            Mode::Value => Err(Fault::ValueModeWrite),
+            Mode::Pointer => self.addr(arg),
+            Mode::Relative => self.relative(arg),
         }
     }
 
@@ -232,7 +535,7 @@ mod tests {
         let test_program: Vec<i64> = [1,9,10,3,2,3,11,0,99,30,40,50].to_vec();
 
         let (_m_in, _m_out, mut mach) = IntcodeMachine::new(test_program);
-        assert_eq!(3500, mach.run_program());
+        assert_eq!(3500, mach.run_program().unwrap());
     }
 
     #[test]
@@ -295,33 +598,33 @@ mod tests {
         let lt_8_ptr: Vec<i64> = [3,9,7,9,10,9,4,9,99,-1,8].to_vec();
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_ptr.to_owned());
         m_in.send(7).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(1, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_ptr.to_owned());
         m_in.send(8).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_ptr.to_owned());
         m_in.send(9).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
 
         let lt_8_val: Vec<i64> = [3,3,1107,-1,8,3,4,3,99].to_vec();
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_val.to_owned());
         m_in.send(7).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(1, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_val.to_owned());
         m_in.send(8).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(lt_8_val.to_owned());
         m_in.send(9).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
     }
 
@@ -333,22 +636,22 @@ mod tests {
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(not_zero_ptr.to_owned());
         m_in.send(0).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(not_zero_ptr.to_owned());
         m_in.send(-11).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(1, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(not_zero_val.to_owned());
         m_in.send(0).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(0, m_out.recv().unwrap());
 
         let (m_in, m_out, mut mach) = IntcodeMachine::new(not_zero_val.to_owned());
         m_in.send(-1).expect("failed to send");
-        mach.run_program();
+        mach.run_program().unwrap();
         assert_eq!(1, m_out.recv().unwrap());
     }
 
@@ -360,7 +663,7 @@ mod tests {
             m_in.send(b).unwrap();
         }
         thread::spawn(move || {
-            mach.run_program();
+            mach.run_program().unwrap();
         });
         let mut i = 0;
         for b in m_out {
@@ -373,7 +676,7 @@ mod tests {
     fn test_big_output() {
         let big_o: Vec<i64> = [1102,34915192,34915192,7,4,7,99,0].to_vec();
         let (_m_in, m_out, mut mach) = IntcodeMachine::new(big_o.to_owned());
-        mach.run_program();
+        mach.run_program().unwrap();
         let o = m_out.recv().unwrap();
         assert!(o > 999_999_999_999_999);
     }
@@ -382,8 +685,177 @@ mod tests {
     fn test_big_io() {
         let big_io: Vec<i64> = [104,1125899906842624,99].to_vec();
         let (_m_in, m_out, mut mach) = IntcodeMachine::new(big_io.to_owned());
-        mach.run_program();
+        mach.run_program().unwrap();
         let o = m_out.recv().unwrap();
         assert!(o == big_io[1]);
     }
+
+    #[test]
+    fn test_faults() {
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([1,0,0,0,42,99].to_vec());
+        assert_eq!(Err(IntcodeError::Machine {
+            ip: 4,
+            instruction: 42,
+            off: 0,
+            fault: Fault::BadOpcode,
+        }), mach.run_program());
+
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([109,7,301,0,0,0,99].to_vec());
+        assert_eq!(Err(IntcodeError::Machine {
+            ip: 2,
+            instruction: 301,
+            off: 7,
+            fault: Fault::BadMode,
+        }), mach.run_program());
+
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([11101,1,1,0,99].to_vec());
+        let err = mach.run_program().unwrap_err();
+        assert_eq!("write parameter in value mode at ip 0 (instruction 11101, relative base 0)",
+                   err.to_string());
+
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([1,-3,0,0,99].to_vec());
+        match mach.run_program() {
+            Err(IntcodeError::Machine { fault, .. }) => {
+                assert_eq!(Fault::NegativeAddress(-3), fault);
+            },
+            other => panic!("expected fault, got {:?}", other),
+        }
+
+        // moving the relative base, or an address relative to it, past i64::MAX
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([109,i64::MAX,109,1,99].to_vec());
+        assert_eq!(Err(IntcodeError::Machine {
+            ip: 2,
+            instruction: 109,
+            off: i64::MAX,
+            fault: Fault::Overflow,
+        }), mach.run_program());
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new([109,1,21101,1,1,i64::MAX,99].to_vec());
+        assert_eq!(Err(IntcodeError::Machine {
+            ip: 2,
+            instruction: 21101,
+            off: 1,
+            fault: Fault::Overflow,
+        }), mach.run_program());
+    }
+
+    #[test]
+    fn test_closed_io() {
+        let (m_in, _m_out, mut mach) = IntcodeMachine::new([3,0,99].to_vec());
+        drop(m_in);
+        match mach.run_program() {
+            Err(IntcodeError::Machine { ip: 0, fault, .. }) => {
+                assert_eq!(Fault::InputClosed, fault);
+            },
+            other => panic!("expected fault, got {:?}", other),
+        }
+
+        let (_m_in, m_out, mut mach) = IntcodeMachine::new([104,1,99].to_vec());
+        drop(m_out);
+        match mach.run_program() {
+            Err(IntcodeError::Machine { ip: 0, fault, .. }) => {
+                assert_eq!(Fault::OutputClosed, fault);
+            },
+            other => panic!("expected fault, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let eq_8_ptr: Vec<i64> = [3,9,8,9,10,9,4,9,99,-1,8].to_vec();
+        let (m_in, m_out, mut mach) = IntcodeMachine::new(eq_8_ptr);
+        assert_eq!(Ok(Some(StopReason::NeedsInput)), mach.step());
+        assert_eq!(Ok(StopReason::NeedsInput), mach.run_until(None));
+        m_in.send(8).unwrap();
+        assert_eq!(Ok(None), mach.step());
+        assert_eq!(Ok(StopReason::Output(1)), mach.run_until(None));
+        assert_eq!(Ok(1), m_out.try_recv());
+        assert_eq!(Ok(StopReason::Halted), mach.run_until(None));
+        assert_eq!(Ok(Some(StopReason::Halted)), mach.step());
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let spin: Vec<i64> = [1105,1,0].to_vec();
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new(spin);
+        assert_eq!(Ok(StopReason::StepLimit), mach.run_until(Some(1000)));
+        assert_eq!(Ok(StopReason::StepLimit), mach.run_until(Some(0)));
+
+        // one budget covers every stop for output
+        let chatter: Vec<i64> = [104,1,1105,1,0].to_vec();
+        let mut mach = IntcodeMachine::with_io(chatter, BufferIo::default());
+        let mut budget = 10;
+        let mut outputs = 0;
+        while let Ok(StopReason::Output(_)) = mach.run_within(&mut budget) {
+            outputs += 1;
+        }
+        assert_eq!((5, 0), (outputs, budget));
+    }
+
+    #[test]
+    fn test_memory_grows() {
+        // a program longer than the old fixed memory size, jumping to its end
+        let mut long: Vec<i64> = vec![1106,0,0x10000];
+        long.resize(0x10000, 0);
+        long.extend([99].iter());
+        let (_m_in, _m_out, mut mach) = IntcodeMachine::new(long);
+        assert_eq!(Ok(1106), mach.run_program());
+
+        // store to and load from a far away cell
+        let far: Vec<i64> = [1101,6,7,1000000000,4,1000000000,99].to_vec();
+        let (_m_in, m_out, mut mach) = IntcodeMachine::new(far);
+        mach.run_program().unwrap();
+        assert_eq!(Ok(13), m_out.recv());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let overflow: Vec<i64> = [1101,i64::MAX,1,5,99,0].to_vec();
+        let mut mach = IntcodeMachine::with_io(overflow.to_owned(), BufferIo::default());
+        assert_eq!(Err(IntcodeError::Machine { ip: 0, instruction: 1101, off: 0, fault: Fault::Overflow }),
+                   mach.run_program());
+
+        let mut mach = IntcodeMachine::with_arithmetic(overflow, BufferIo::default(), Arithmetic::Wrapping);
+        mach.run_program().unwrap();
+        assert_eq!(i64::MIN, mach.peek(5));
+
+        // squares 2^40, compares the square and cancels it out, then outputs
+        // it, which it is too big for
+        let wide: Vec<i64> = [
+            1102,1<<40,1<<40,30,
+            8,30,30,31,
+            1007,30,i64::MAX,32,
+            1002,30,-1,33,
+            1,30,33,34,
+            4,34,
+            4,31,
+            4,30,
+            99,
+        ].to_vec();
+        let mut mach = IntcodeMachine::with_arithmetic(wide.to_owned(), BufferIo::default(), Arithmetic::Widened);
+        assert_eq!(Err(IntcodeError::Machine { ip: 24, instruction: 4, off: 0, fault: Fault::Overflow }),
+                   mach.run_program());
+        assert_eq!(vec![0, 1], Vec::from(mach.io_mut().output.to_owned()));
+        assert_eq!(0, mach.peek(32));
+
+        let mut mach = IntcodeMachine::with_io(wide, BufferIo::default());
+        assert_eq!(Err(IntcodeError::Machine { ip: 0, instruction: 1102, off: 0, fault: Fault::Overflow }),
+                   mach.run_program());
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // outputs its own operand, bumping it each time round until it is 9
+        let bump: Vec<i64> = [104,7,1001,1,1,1,1007,1,9,14,1005,14,0,99,0].to_vec();
+        let mut mach = IntcodeMachine::with_io(bump.to_owned(), BufferIo::default());
+        mach.run_program().unwrap();
+        assert_eq!(vec![7,8], Vec::from(mach.io_mut().output.to_owned()));
+
+        // poking the program also replaces the decoded instruction
+        let mut mach = IntcodeMachine::with_io(bump, BufferIo::default());
+        assert_eq!(Ok(StopReason::Output(7)), mach.run_until(None));
+        mach.poke(0, 99);
+        mach.run_program().unwrap();
+        assert_eq!(0, mach.ip());
+        assert_eq!(vec![7], Vec::from(mach.io_mut().output.to_owned()));
+    }
 }
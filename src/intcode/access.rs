@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use super::{BufferIo, IntcodeError, IntcodeMachine, StopReason};
+use super::trace::{TraceEvent, TraceSink};
+
+// most instructions an analysis runs, in case the program never stops
+const ANALYSIS_LIMIT: usize = 100_000_000;
+
+// How one cell of memory was used while the program ran
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Access {
+    // part of an instruction which executed, opcode or operand
+    pub executed: bool,
+    pub read: bool,
+    pub written: bool,
+}
+
+impl Access {
+    fn data(&self) -> bool {
+        !self.executed && (self.read || self.written)
+    }
+}
+
+// A map of which cells a run of the program used as code and which as data,
+// built from its trace.
+#[derive(Default)]
+pub struct AccessMap {
+    cells: BTreeMap<usize, Access>,
+    // addresses that instructions were executed from
+    starts: BTreeSet<usize>,
+    // (address of the writing instruction, address written) for every write
+    writes: BTreeSet<(usize, usize)>,
+}
+
+impl TraceSink for AccessMap {
+    fn record(&mut self, event: &TraceEvent) {
+        self.starts.insert(event.ip);
+        for addr in event.ip..event.ip + event.instruction.len() {
+            self.cells.entry(addr).or_default().executed = true;
+        }
+        for addr in event.reads.iter() {
+            self.cells.entry(*addr).or_default().read = true;
+        }
+        if let Some((addr, _)) = event.write {
+            self.cells.entry(addr).or_default().written = true;
+            self.writes.insert((event.ip, addr));
+        }
+    }
+}
+
+impl AccessMap {
+    pub fn get(&self, addr: usize) -> Access {
+        self.cells.get(&addr).cloned().unwrap_or_default()
+    }
+
+    // whether an instruction was executed from `addr`
+    pub fn start(&self, addr: usize) -> bool {
+        self.starts.contains(&addr)
+    }
+
+    // Writes which landed on code, as (writer, address written).  The code
+    // may have run before the write, after it, or both.
+    pub fn self_modifications(&self) -> Vec<(usize, usize)> {
+        self.writes.iter()
+            .filter(|(_, addr)| self.get(*addr).executed)
+            .cloned()
+            .collect()
+    }
+
+    // Writes the runs of cells used as code and as data, and the writes
+    // which modified code.
+    pub fn report<W: Write>(&self, mut out: W) {
+        let code: Vec<usize> = self.cells.iter().filter(|(_, a)| a.executed).map(|(addr, _)| *addr).collect();
+        let data: Vec<usize> = self.cells.iter().filter(|(_, a)| a.data()).map(|(addr, _)| *addr).collect();
+        writeln!(out, "code: {}", ranges(&code)).unwrap();
+        writeln!(out, "data: {}", ranges(&data)).unwrap();
+        let modified = self.self_modifications();
+        if !modified.is_empty() {
+            writeln!(out, "modified code:").unwrap();
+        }
+        for (ip, addr) in modified {
+            writeln!(out, "  {:04} written by {:04}", addr, ip).unwrap();
+        }
+    }
+}
+
+// Formats sorted addresses as runs, e.g. 0000-0008, 0012
+fn ranges(addrs: &[usize]) -> String {
+    if addrs.is_empty() {
+        return "none".to_string();
+    }
+    let mut runs: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < addrs.len() {
+        let mut j = i;
+        while j + 1 < addrs.len() && addrs[j + 1] == addrs[j] + 1 {
+            j += 1;
+        }
+        if i == j {
+            runs.push(format!("{:04}", addrs[i]));
+        } else {
+            runs.push(format!("{:04}-{:04}", addrs[i], addrs[j]));
+        }
+        i = j + 1;
+    }
+    runs.join(", ")
+}
+
+// Runs `program` on `input` and maps how it used its memory.  The run stops
+// when the program halts or wants more input than it was given.
+pub fn analyse(program: Vec<i64>, input: Vec<i64>) -> Result<AccessMap, IntcodeError> {
+    let map = Arc::new(Mutex::new(AccessMap::default()));
+    let mut mach = IntcodeMachine::with_io(program, BufferIo::new(input));
+    mach.trace(map.clone());
+    // output only pauses the run, anything else ends it
+    let mut budget = ANALYSIS_LIMIT;
+    while let StopReason::Output(_) = mach.run_within(&mut budget)? {}
+    drop(mach);
+    let map = Arc::try_unwrap(map).ok().expect("trace still shared");
+    Ok(map.into_inner().unwrap())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyse() {
+        // day 2's example: adds 30 and 40 into cell 3, then multiplies the
+        // operands of the first instruction into cell 0
+        let program: Vec<i64> = [1,9,10,3,2,3,11,0,99,30,40,50].to_vec();
+        let map = analyse(program, vec![]).unwrap();
+        assert_eq!(Access { executed: true, read: true, written: true }, map.get(3));
+        assert_eq!(Access { executed: false, read: true, written: false }, map.get(9));
+        assert_eq!(Access::default(), map.get(12));
+        assert!(map.start(4));
+        assert!(!map.start(3));
+        assert_eq!(vec![(0, 3), (4, 0)], map.self_modifications());
+
+        let mut report = Vec::new();
+        map.report(&mut report);
+        assert_eq!("\
+code: 0000-0008
+data: 0009-0011
+modified code:
+  0003 written by 0000
+  0000 written by 0004
+", String::from_utf8(report).unwrap());
+    }
+
+    #[test]
+    fn test_relative_reads() {
+        // reads its input back from above the relative base
+        let program: Vec<i64> = [109,20,203,1,204,1,99].to_vec();
+        let map = analyse(program, vec![7]).unwrap();
+        assert_eq!(Access { executed: false, read: true, written: true }, map.get(21));
+        assert!(map.self_modifications().is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+use super::Fault;
+
+// What `add` and `mul` do when a result doesn't fit in 64 bits
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Arithmetic {
+    // wrap around, as release builds of plain i64 arithmetic do
+    Wrapping,
+    // fault, so that the machine stops with the ip of the instruction
+    #[default]
+    Checked,
+    // carry on in 128 bits; a value too big for 64 can still be computed
+    // with and compared, but faults if used as an address or output
+    Widened,
+}
+
+impl fmt::Display for Arithmetic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arithmetic::Wrapping => write!(f, "wrapping"),
+            Arithmetic::Checked => write!(f, "checked"),
+            Arithmetic::Widened => write!(f, "widened"),
+        }
+    }
+}
+
+impl FromStr for Arithmetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Arithmetic, String> {
+        match s {
+            "wrapping" => Ok(Arithmetic::Wrapping),
+            "checked" => Ok(Arithmetic::Checked),
+            "widened" => Ok(Arithmetic::Widened),
+            _ => Err(format!("unknown arithmetic {:?}", s)),
+        }
+    }
+}
+
+impl Arithmetic {
+    // Operands are always 128 bits wide, but only widened arithmetic ever
+    // sees values which don't fit in 64.
+    pub(super) fn add(self, a: i128, b: i128) -> Result<i128, Fault> {
+        match self {
+            Arithmetic::Wrapping => Ok((a as i64).wrapping_add(b as i64) as i128),
+            Arithmetic::Checked => (a as i64).checked_add(b as i64).map(i128::from).ok_or(Fault::Overflow),
+            Arithmetic::Widened => a.checked_add(b).ok_or(Fault::Overflow),
+        }
+    }
+
+    pub(super) fn mul(self, a: i128, b: i128) -> Result<i128, Fault> {
+        match self {
+            Arithmetic::Wrapping => Ok((a as i64).wrapping_mul(b as i64) as i128),
+            Arithmetic::Checked => (a as i64).checked_mul(b as i64).map(i128::from).ok_or(Fault::Overflow),
+            Arithmetic::Widened => a.checked_mul(b).ok_or(Fault::Overflow),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies() {
+        let max = i64::MAX as i128;
+        assert_eq!(Ok(i64::MIN as i128), Arithmetic::Wrapping.add(max, 1));
+        assert_eq!(Err(Fault::Overflow), Arithmetic::Checked.add(max, 1));
+        assert_eq!(Ok(max + 1), Arithmetic::Widened.add(max, 1));
+
+        assert_eq!(Ok(-2), Arithmetic::Wrapping.mul(max, 2));
+        assert_eq!(Err(Fault::Overflow), Arithmetic::Checked.mul(max, 2));
+        assert_eq!(Ok(max * 2), Arithmetic::Widened.mul(max, 2));
+        assert_eq!(Err(Fault::Overflow), Arithmetic::Widened.mul(max * max, 4));
+
+        assert_eq!(Ok(6), Arithmetic::Checked.mul(2, 3));
+        assert_eq!(Ok(Arithmetic::Widened), "widened".parse());
+        assert_eq!("wrapping", Arithmetic::Wrapping.to_string());
+        assert!("saturating".parse::<Arithmetic>().is_err());
+    }
+}
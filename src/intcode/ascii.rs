@@ -0,0 +1,158 @@
+use super::{BufferIo, IntcodeError, IntcodeMachine, Snapshot, StopReason};
+
+// What an AsciiMachine printed before it stopped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Response {
+    // text output, split into lines; a prompt without a newline is the last
+    pub lines: Vec<String>,
+    // output which isn't ASCII, usually the answer being looked for
+    pub values: Vec<i64>,
+    pub halted: bool,
+    // the instruction limit ran out first, so the program may say more
+    pub limit_reached: bool,
+}
+
+// A machine which talks in text: output is decoded into lines, and input is
+// sent a string at a time.
+#[derive(Clone)]
+pub struct AsciiMachine {
+    mach: IntcodeMachine<BufferIo>,
+}
+
+impl AsciiMachine {
+    pub fn new(program: Vec<i64>) -> AsciiMachine {
+        AsciiMachine {
+            mach: IntcodeMachine::with_io(program, BufferIo::default()),
+        }
+    }
+
+    // Carries on from a snapshot, including any input it had queued.
+    pub fn resume(snap: &Snapshot) -> AsciiMachine {
+        AsciiMachine {
+            mach: snap.restore(BufferIo::new(snap.input.to_owned())),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.mach.snapshot()
+    }
+
+    // Queues `text` as input, a byte at a time.  Commands are read up to a
+    // newline, so that usually ends the text.
+    pub fn send(&mut self, text: &str) {
+        self.mach.io_mut().input.extend(text.bytes().map(|b| b as i64));
+    }
+
+    // Runs until the program halts or wants input which hasn't been sent.
+    pub fn run(&mut self) -> Result<Response, IntcodeError> {
+        self.run_for(usize::MAX)
+    }
+
+    // As run, but gives up after `limit` instructions, for programs which
+    // may never stop, returning what was printed so far.
+    pub fn run_for(&mut self, limit: usize) -> Result<Response, IntcodeError> {
+        let mut res = Response::default();
+        let mut line = String::new();
+        for _ in 0..limit {
+            match self.mach.step()? {
+                None => continue,
+                Some(StopReason::Output(v)) => {
+                    self.mach.io_mut().output.clear();
+                    match v {
+                        10 => res.lines.push(line.split_off(0)),
+                        0..=127 => line.push(v as u8 as char),
+                        _ => res.values.push(v),
+                    }
+                    continue;
+                },
+                Some(StopReason::Halted) => res.halted = true,
+                Some(_) => {},
+            }
+            if !line.is_empty() {
+                res.lines.push(line);
+            }
+            return Ok(res);
+        }
+        if !line.is_empty() {
+            res.lines.push(line);
+        }
+        res.limit_reached = true;
+        Ok(res)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assemble;
+
+    // prints a prompt, then echoes lines back until it reads an empty one,
+    // and finally prints a thousand times how many lines there were
+    const ECHO: &str = "
+            out #62
+            out #32
+    loop:   in [c]
+            out [c]
+            eq [c], #10, [t]
+            jf [t], #char
+            jt [empty], #done
+            add [n], #1, [n]
+            add #1, #0, [empty]
+            jt #1, #loop
+    char:   add #0, #0, [empty]
+            jt #1, #loop
+    done:   mul [n], #1000, [n]
+            out [n]
+            hlt
+    c:      db 0
+    t:      db 0
+    n:      db 0
+    empty:  db 1
+    ";
+
+    #[test]
+    fn test_lines_and_values() {
+        let mut mach = AsciiMachine::new(assemble(ECHO).unwrap());
+        assert_eq!(Ok(Response {
+            lines: vec!["> ".to_string()],
+            values: vec![],
+            halted: false,
+            limit_reached: false,
+        }), mach.run());
+
+        mach.send("hello\nworld\n");
+        let res = mach.run().unwrap();
+        assert_eq!(vec!["hello", "world"], res.lines);
+        assert!(!res.halted);
+
+        mach.send("\n");
+        assert_eq!(Ok(Response {
+            lines: vec!["".to_string()],
+            values: vec![2000],
+            halted: true,
+            limit_reached: false,
+        }), mach.run());
+    }
+
+    #[test]
+    fn test_run_for() {
+        // prints a line and a prompt, and then never asks for anything
+        let spin: Vec<i64> = [104,104,104,105,104,10,104,62,1105,1,8].to_vec();
+        let mut mach = AsciiMachine::new(spin);
+        assert_eq!(Ok(Response {
+            lines: vec!["hi".to_string(), ">".to_string()],
+            values: vec![],
+            halted: false,
+            limit_reached: true,
+        }), mach.run_for(1000));
+
+        let mut mach = AsciiMachine::new(assemble(ECHO).unwrap());
+        assert_eq!(Ok(Response {
+            lines: vec!["> ".to_string()],
+            values: vec![],
+            halted: false,
+            limit_reached: false,
+        }), mach.run_for(3));
+    }
+}
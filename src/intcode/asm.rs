@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use super::{IntcodeError, Mode};
+use super::disasm::{Op, OPS};
+
+// Assembles source text into a program ready for IntcodeMachine.
+//
+// Each line holds an instruction or a directive, optionally preceded by
+// labels and followed by a `;` comment:
+//
+//   start:  in [n]            ; pointer operand
+//   loop:   out [rb-1]        ; relative operand
+//           add [n], #-1, [n] ; value operand
+//           jt [n], #loop     ; labels may be used wherever numbers can,
+//           hlt               ; plus or minus a constant: #loop+2
+//   n:      db 0              ; literal words
+//   buf:    ds 10             ; ten zeroed words
+//
+// Mnemonics are add, mul, in, out, jt, jf, lt, eq, arb and hlt, in any case.
+// A numeric label such as `0012:` asserts the address of the line instead of
+// naming it, so listings from the disassembler assemble back unchanged.
+pub fn assemble(source: &str) -> Result<Vec<i64>, IntcodeError> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut addr = 0;
+
+    // first pass: find out where everything goes
+    for (n, line) in source.lines().enumerate() {
+        let line_no = n + 1;
+        let err = |message: String| IntcodeError::Asm { line: line_no, message };
+        let mut rest = line.split(';').next().unwrap().trim();
+
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            if label.chars().all(|c| c.is_ascii_digit()) && !label.is_empty() {
+                let expected: usize = label.parse().map_err(|_| err(format!("bad address {}", label)))?;
+                if expected != addr {
+                    return Err(err(format!("address {} is really {}", label, addr)));
+                }
+            } else if is_identifier(label) {
+                if labels.insert(label.to_string(), addr as i64).is_some() {
+                    return Err(err(format!("label {} defined twice", label)));
+                }
+            } else {
+                return Err(err(format!("bad label {:?}", label)));
+            }
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+        let operands: Vec<String> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(|o| o.trim().to_string()).collect()
+        };
+
+        let item = match mnemonic.to_ascii_uppercase().as_ref() {
+            "DB" => Item::Data(operands),
+            "DS" => {
+                let len = match operands.as_slice() {
+                    [len] => len.parse().map_err(|_| err(format!("bad size {}", len)))?,
+                    _ => return Err(err("ds takes one size".to_string())),
+                };
+                Item::Space(len)
+            },
+            name => {
+                let op = OPS.iter()
+                            .find(|op| op.name == name)
+                            .ok_or_else(|| err(format!("unknown mnemonic {}", mnemonic)))?;
+                if operands.len() != op.params {
+                    return Err(err(format!("{} takes {} operands", op.name, op.params)));
+                }
+                let mut args = Vec::new();
+                for (i, operand) in operands.into_iter().enumerate() {
+                    let (mode, expr) = parse_operand(&operand)
+                        .ok_or_else(|| err(format!("bad operand {:?}", operand)))?;
+                    if op.writes && i == op.params - 1 && mode == Mode::Value {
+                        return Err(err(format!("{} cannot write to a value", op.name)));
+                    }
+                    args.push((mode, expr));
+                }
+                Item::Instr(op, args)
+            },
+        };
+        addr += item.len();
+        items.push((line_no, item));
+    }
+
+    // second pass: resolve labels and emit words
+    let mut program = Vec::with_capacity(addr);
+    for (line_no, item) in items {
+        let eval = |expr: &str| {
+            evaluate(expr, &labels).map_err(|message| IntcodeError::Asm { line: line_no, message })
+        };
+        match item {
+            Item::Instr(op, args) => {
+                let mut word = op.code;
+                let mut place = 100;
+                for (mode, _) in args.iter() {
+                    word += place * match mode {
+                        Mode::Pointer => 0,
+                        Mode::Value => 1,
+                        Mode::Relative => 2,
+                    };
+                    place *= 10;
+                }
+                program.push(word);
+                for (_, expr) in args.iter() {
+                    program.push(eval(expr)?);
+                }
+            },
+            Item::Data(exprs) => {
+                for expr in exprs.iter() {
+                    program.push(eval(expr)?);
+                }
+            },
+            Item::Space(len) => {
+                program.extend(vec![0; len]);
+            },
+        }
+    }
+    Ok(program)
+}
+
+enum Item {
+    Instr(&'static Op, Vec<(Mode, String)>),
+    Data(Vec<String>),
+    Space(usize),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instr(op, _) => op.params + 1,
+            Item::Data(words) => words.len(),
+            Item::Space(len) => *len,
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        },
+        _ => false,
+    }
+}
+
+// splits `#5`, `[104]` or `[rb+3]` into its mode and value expression
+fn parse_operand(operand: &str) -> Option<(Mode, String)> {
+    if let Some(expr) = operand.strip_prefix('#') {
+        return Some((Mode::Value, expr.trim().to_string()));
+    }
+    let inner = operand.strip_prefix('[')?.strip_suffix(']')?.trim();
+    match inner.strip_prefix("rb") {
+        Some("") => Some((Mode::Relative, "0".to_string())),
+        Some(off) if off.starts_with('+') || off.starts_with('-') => {
+            Some((Mode::Relative, off.to_string()))
+        },
+        _ => Some((Mode::Pointer, inner.to_string())),
+    }
+}
+
+// evaluates a sum of numbers and labels, such as `-1`, `loop` or `buf+3-1`
+fn evaluate(expr: &str, labels: &HashMap<String, i64>) -> Result<i64, String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    for c in expr.chars().filter(|c| !c.is_whitespace()) {
+        if (c == '+' || c == '-') && !term.is_empty() && term != "+" && term != "-" {
+            terms.push(term);
+            term = String::new();
+        }
+        term.push(c);
+    }
+    terms.push(term);
+
+    let overflow = || format!("value {:?} overflows", expr);
+    let mut total: i64 = 0;
+    for term in terms.iter() {
+        // numbers are parsed along with their sign, so that i64::MIN can be
+        // written as it is disassembled
+        let value = match term.parse::<i64>() {
+            Ok(v) => v,
+            Err(_) => {
+                let (negate, name) = match term.chars().next() {
+                    Some('-') => (true, &term[1..]),
+                    Some('+') => (false, &term[1..]),
+                    _ => (false, &term[..]),
+                };
+                let value = match labels.get(name) {
+                    Some(v) => *v,
+                    None if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) => return Err(overflow()),
+                    None => return Err(format!("bad value {:?}", expr)),
+                };
+                if negate {
+                    value.checked_neg().ok_or_else(overflow)?
+                } else {
+                    value
+                }
+            },
+        };
+        total = total.checked_add(value).ok_or_else(overflow)?;
+    }
+    Ok(total)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{disassemble, BufferIo, IntcodeMachine};
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; is the input equal to 8?
+            in [x]
+            eq [x], [eight], [x]
+            OUT [x]
+            HLT
+        x:      db -1
+        eight:  db 8
+        ";
+        assert_eq!(Ok(vec![3,9,8,9,10,9,4,9,99,-1,8]), assemble(source));
+    }
+
+    #[test]
+    fn test_labels_and_offsets() {
+        // counts down from the input, outputting each step
+        let source = "
+                in [n]
+        loop:   out [n]
+                add [n], #-1, [n]
+                jt [n], #loop
+                hlt
+        n:      ds 1
+        after:  db after-n, loop+1
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(vec![3,12,4,12,1001,12,-1,12,1005,12,2,99,0,1,3], program);
+
+        let mut mach = IntcodeMachine::with_io(program, BufferIo::new(vec![3]));
+        mach.run_program().unwrap();
+        assert_eq!(vec![3,2,1], Vec::from(mach.io_mut().output.to_owned()));
+    }
+
+    #[test]
+    fn test_relative_operands() {
+        let source = "arb #5\nout [rb-1]\nin [rb]\nadd [rb+2], #1, [rb+3]\nhlt";
+        assert_eq!(Ok(vec![109,5,204,-1,203,0,21201,2,1,3,99]), assemble(source));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |line, message: &str| Err(IntcodeError::Asm { line, message: message.to_string() });
+        assert_eq!(err(2, "unknown mnemonic jmp"), assemble("hlt\njmp #0"));
+        assert_eq!(err(1, "ADD takes 3 operands"), assemble("add [1], [2]"));
+        assert_eq!(err(1, "IN cannot write to a value"), assemble("in #1"));
+        assert_eq!(err(1, "bad operand \"5\""), assemble("out 5"));
+        assert_eq!(err(1, "bad value \"nowhere\""), assemble("jt #1, #nowhere"));
+        assert_eq!(err(2, "label a defined twice"), assemble("a: hlt\na: hlt"));
+        assert_eq!(err(2, "address 0003 is really 1"), assemble("hlt\n0003: hlt"));
+        assert_eq!(err(1, "value \"9223372036854775807+1\" overflows"), assemble("db 9223372036854775807+1"));
+        assert_eq!(err(1, "value \"99999999999999999999\" overflows"), assemble("db 99999999999999999999"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let programs: Vec<Vec<i64>> = vec![
+            [3,9,8,9,10,9,4,9,99,-1,8].to_vec(),
+            [3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9].to_vec(),
+            [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99].to_vec(),
+            [1102,34915192,34915192,7,4,7,99,0].to_vec(),
+            [11101,-1,-1,-1,301,-1,-1,-1,1099,22201,0,0,0,1,0,0].to_vec(),
+            [1101,i64::MIN,i64::MAX,0,104,i64::MIN,99].to_vec(),
+            crate::intcode::read_program(&include_bytes!("../../9/input")[..]).unwrap(),
+        ];
+        for p in programs {
+            let listing = disassemble(&p);
+            assert_eq!(Ok(p), assemble(&listing), "listing:\n{}", listing);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use super::{Fault, Mode};
+use super::memory::Memory;
+
+// An instruction as the interpreter executes it: the opcode, the mode of each
+// parameter (Pointer when not given) and the words following the opcode.
+// Instructions with fewer than three parameters simply ignore the rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decoded {
+    pub opcode: u8,
+    pub modes: [Mode; 3],
+    pub args: [i64; 3],
+}
+
+// Splits an instruction word into its opcode and parameter modes.  Every mode
+// digit must be valid, even those beyond the parameters the opcode takes.
+pub fn decode_word(word: i64) -> Result<(u8, [Mode; 3]), Fault> {
+    if word < 0 {
+        return Err(Fault::BadOpcode);
+    }
+    let mut modes = [Mode::Pointer; 3];
+    let mut digits = word / 100;
+    let mut i = 0;
+    while digits > 0 {
+        let mode = match digits % 10 {
+            0 => Mode::Pointer,
+            1 => Mode::Value,
+            2 => Mode::Relative,
+            _ => return Err(Fault::BadMode),
+        };
+        if i < modes.len() {
+            modes[i] = mode;
+        }
+        digits /= 10;
+        i += 1;
+    }
+    Ok(((word % 100) as u8, modes))
+}
+
+// Decodes the instruction at `ip` the way the interpreter did before it had
+// decode_word and the cache: the modes are pushed to a Vec and reversed, and
+// the arguments gathered into a VecDeque, every time.  Only kept as a
+// baseline for benchmarks.
+pub fn decode_allocating(memory: &Memory, ip: usize) -> Result<Decoded, Fault> {
+    let mut word = memory.get(ip);
+    if word < 0 {
+        return Err(Fault::BadOpcode);
+    }
+    let opcode = (word % 100) as u8;
+    word /= 100;
+    let mut modes = Vec::new();
+    while word > 0 {
+        modes.push(match word % 10 {
+            0 => Mode::Pointer,
+            1 => Mode::Value,
+            2 => Mode::Relative,
+            _ => return Err(Fault::BadMode),
+        });
+        word /= 10;
+    }
+    while modes.len() < 3 {
+        modes.push(Mode::Pointer);
+    }
+    modes.reverse();
+    let mut args: VecDeque<i64> = (1..4).map(|n| memory.get(ip + n)).collect();
+    let mut decoded = Decoded { opcode, modes: [Mode::Pointer; 3], args: [0; 3] };
+    for n in 0..3 {
+        decoded.modes[n] = modes.pop().unwrap();
+        decoded.args[n] = args.pop_front().unwrap();
+    }
+    Ok(decoded)
+}
+
+// Decoded instructions by the address they start at.  Any write to memory
+// must be reported with `invalidate` so that self-modifying programs never
+// execute a stale decoding.
+#[derive(Clone, Default)]
+pub struct DecodeCache {
+    slots: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+    pub fn get(&self, addr: usize) -> Option<Decoded> {
+        self.slots.get(addr).copied().flatten()
+    }
+
+    pub fn insert(&mut self, addr: usize, decoded: Decoded) {
+        if addr >= self.slots.len() {
+            self.slots.resize(addr + 1, None);
+        }
+        self.slots[addr] = Some(decoded);
+    }
+
+    // Forgets every instruction which covers `addr`.
+    pub fn invalidate(&mut self, addr: usize) {
+        for start in addr.saturating_sub(3)..=addr {
+            if let Some(slot) = self.slots.get_mut(start) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_word() {
+        assert_eq!(Ok((2, [Mode::Pointer, Mode::Value, Mode::Pointer])), decode_word(1002));
+        assert_eq!(Ok((1, [Mode::Relative, Mode::Value, Mode::Relative])), decode_word(21201));
+        assert_eq!(Ok((99, [Mode::Pointer; 3])), decode_word(99));
+        assert_eq!(Ok((1, [Mode::Value; 3])), decode_word(1111101));
+        assert_eq!(Err(Fault::BadMode), decode_word(301));
+        assert_eq!(Err(Fault::BadMode), decode_word(3001101));
+        assert_eq!(Err(Fault::BadOpcode), decode_word(-1));
+    }
+
+    #[test]
+    fn test_decode_allocating() {
+        let memory = Memory::new(vec![21201, 5, -6, 7, 301, 1002]);
+        assert_eq!(Ok(Decoded {
+            opcode: 1,
+            modes: [Mode::Relative, Mode::Value, Mode::Relative],
+            args: [5, -6, 7],
+        }), decode_allocating(&memory, 0));
+        assert_eq!(Err(Fault::BadMode), decode_allocating(&memory, 4));
+        assert_eq!(Ok((2, [Mode::Pointer, Mode::Value, Mode::Pointer])),
+                   decode_allocating(&memory, 5).map(|d| (d.opcode, d.modes)));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let decoded = Decoded { opcode: 99, modes: [Mode::Pointer; 3], args: [0; 3] };
+        let mut cache = DecodeCache::default();
+        cache.insert(0, decoded);
+        cache.insert(4, decoded);
+        cache.insert(8, decoded);
+
+        cache.invalidate(7);
+        assert_eq!(Some(decoded), cache.get(0));
+        assert_eq!(None, cache.get(4));
+        assert_eq!(Some(decoded), cache.get(8));
+        cache.invalidate(100);
+        assert_eq!(Some(decoded), cache.get(8));
+        assert_eq!(None, cache.get(100));
+    }
+}
@@ -0,0 +1,272 @@
+use std::sync::Arc;
+use super::{Arithmetic, Fault, Mode};
+use super::cache::decode_word;
+use super::memory::Memory;
+
+// An operand with its mode already decided
+#[derive(Clone, Copy)]
+enum Operand {
+    Value(i64),
+    Cell(usize),
+    Relative(i64),
+}
+
+impl Operand {
+    fn new(mode: Mode, arg: i64) -> Option<Operand> {
+        match mode {
+            Mode::Value => Some(Operand::Value(arg)),
+            // a negative address is left for the interpreter to fault on
+            Mode::Pointer if arg < 0 => None,
+            Mode::Pointer => Some(Operand::Cell(arg as usize)),
+            Mode::Relative => Some(Operand::Relative(arg)),
+        }
+    }
+
+    fn addr(self, off: i64) -> Result<usize, Fault> {
+        match self {
+            Operand::Cell(addr) => Ok(addr),
+            Operand::Relative(arg) => match arg.checked_add(off).ok_or(Fault::Overflow)? {
+                addr if addr < 0 => Err(Fault::NegativeAddress(addr)),
+                addr => Ok(addr as usize),
+            },
+            Operand::Value(_) => Err(Fault::ValueModeWrite),
+        }
+    }
+
+    // the operand's value, for arithmetic and comparisons
+    fn wide(self, memory: &Memory, off: i64) -> Result<i128, Fault> {
+        match self {
+            Operand::Value(v) => Ok(v as i128),
+            _ => Ok(memory.get_wide(self.addr(off)?)),
+        }
+    }
+
+    // the operand's value, which must fit in 64 bits
+    fn narrow(self, memory: &Memory, off: i64) -> Result<i64, Fault> {
+        match self {
+            Operand::Value(v) => Ok(v),
+            _ => {
+                let addr = self.addr(off)?;
+                if memory.is_wide(addr) {
+                    return Err(Fault::Overflow);
+                }
+                Ok(memory.get(addr))
+            },
+        }
+    }
+}
+
+// What a compiled instruction asks the machine to do once it has run
+pub enum Effect {
+    Write(usize, i128),
+    Jump(usize),
+    Next,
+}
+
+// One instruction, bound to its operands.  It may move the relative base
+// itself, but leaves writes to the machine so that they are seen by
+// everything which watches memory.
+pub type Op = Arc<dyn Fn(&Memory, &mut i64, Arithmetic) -> Result<Effect, Fault> + Send + Sync>;
+
+// A program translated ahead of time into a closure for every address an
+// instruction could start at.  Only instructions which compute or jump are
+// compiled; input, output, halting and anything which would fault on decoding
+// are left to the interpreter, as is any instruction since written over.
+#[derive(Clone)]
+pub struct Compiled {
+    // each op and its length in words; shared between clones of a machine
+    // until one of them writes over its code
+    ops: Arc<Vec<Option<(Op, usize)>>>,
+}
+
+impl Compiled {
+    pub fn new(program: &[i64]) -> Compiled {
+        let ops = (0..program.len()).map(|addr| compile(program, addr)).collect();
+        Compiled { ops: Arc::new(ops) }
+    }
+
+    pub fn get(&self, addr: usize) -> Option<&(Op, usize)> {
+        self.ops.get(addr).and_then(Option::as_ref)
+    }
+
+    // Forgets every op which covers `addr`, returning whether there were any.
+    pub fn invalidate(&mut self, addr: usize) -> bool {
+        let stale: Vec<usize> = (addr.saturating_sub(3)..=addr)
+            .filter(|start| self.get(*start).is_some_and(|(_, len)| start + len > addr))
+            .collect();
+        if stale.is_empty() {
+            return false;
+        }
+        let ops = Arc::make_mut(&mut self.ops);
+        for start in stale {
+            ops[start] = None;
+        }
+        true
+    }
+}
+
+fn compile(program: &[i64], addr: usize) -> Option<(Op, usize)> {
+    let (opcode, modes) = decode_word(program[addr]).ok()?;
+    let arg = |n: usize| program.get(addr + n + 1).copied().unwrap_or(0);
+    let a = Operand::new(modes[0], arg(0))?;
+    let b = Operand::new(modes[1], arg(1))?;
+    let c = Operand::new(modes[2], arg(2))?;
+    let op: Op = match opcode {
+        1 | 2 | 7 | 8 if modes[2] == Mode::Value => return None,
+        1 => Arc::new(move |mem, off, arith| {
+            let sum = arith.add(a.wide(mem, *off)?, b.wide(mem, *off)?)?;
+            Ok(Effect::Write(c.addr(*off)?, sum))
+        }),
+        2 => Arc::new(move |mem, off, arith| {
+            let prod = arith.mul(a.wide(mem, *off)?, b.wide(mem, *off)?)?;
+            Ok(Effect::Write(c.addr(*off)?, prod))
+        }),
+        5 | 6 => Arc::new(move |mem, off, _| {
+            if (a.narrow(mem, *off)? != 0) != (opcode == 5) {
+                return Ok(Effect::Next);
+            }
+            match b.narrow(mem, *off)? {
+                to if to < 0 => Err(Fault::NegativeAddress(to)),
+                to => Ok(Effect::Jump(to as usize)),
+            }
+        }),
+        7 => Arc::new(move |mem, off, _| {
+            let less = a.wide(mem, *off)? < b.wide(mem, *off)?;
+            Ok(Effect::Write(c.addr(*off)?, less as i128))
+        }),
+        8 => Arc::new(move |mem, off, _| {
+            let equal = a.wide(mem, *off)? == b.wide(mem, *off)?;
+            Ok(Effect::Write(c.addr(*off)?, equal as i128))
+        }),
+        9 => Arc::new(move |mem, off, _| {
+            *off = off.checked_add(a.narrow(mem, *off)?).ok_or(Fault::Overflow)?;
+            Ok(Effect::Next)
+        }),
+        _ => return None,
+    };
+    let len = match opcode {
+        5 | 6 => 3,
+        9 => 2,
+        _ => 4,
+    };
+    Some((op, len))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::intcode::{BufferIo, IntcodeError, IntcodeMachine, StopReason};
+
+    // Runs `program` on `input` with both backends, and checks that they
+    // finish the same way, with the same output and the same memory.
+    fn same(program: &[i64], input: &[i64]) -> Result<i64, IntcodeError> {
+        let run = |compiled: bool| {
+            let mut mach = IntcodeMachine::with_io(program.to_vec(), BufferIo::new(input.to_vec()));
+            if compiled {
+                mach.compile();
+            }
+            let res = mach.run_program();
+            (res, mach.ip(), mach.relative_base(), mach.snapshot().to_string())
+        };
+        let interpreted = run(false);
+        assert_eq!(interpreted, run(true), "{:?} on {:?}", program, input);
+        interpreted.0
+    }
+
+    fn day(n: u32) -> Vec<i64> {
+        crate::intcode::load_program(format!("{}/input", n)).unwrap()
+    }
+
+    #[test]
+    fn test_examples() {
+        let eq_8_ptr = [3,9,8,9,10,9,4,9,99,-1,8];
+        let lt_8_imm = [3,3,1107,-1,8,3,4,3,99];
+        let jmp_ptr = [3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
+        let cmp_8 = [3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+                     1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+                     999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
+        for input in -2..12 {
+            same(&eq_8_ptr, &[input]).unwrap();
+            same(&lt_8_imm, &[input]).unwrap();
+            same(&jmp_ptr, &[input]).unwrap();
+            same(&cmp_8, &[input]).unwrap();
+        }
+        let quine = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        same(&quine, &[]).unwrap();
+        same(&[1102,34915192,34915192,7,4,7,99,0], &[]).unwrap();
+        // self-modifying: the ADD writes a HLT over the instruction after it
+        same(&[1101,0,99,4,1101,0,0,0], &[]).unwrap();
+    }
+
+    #[test]
+    fn test_faults() {
+        // bad opcode, negative address, relative address gone negative,
+        // jump to a negative address, overflow, input run dry, the relative
+        // base and an address relative to it going past i64::MAX
+        for program in [
+            vec![1101,1,1,0,42],
+            vec![1,-1,0,0,99],
+            vec![109,-5,21101,1,1,0,99],
+            vec![1105,1,-3],
+            vec![1101,i64::MAX,1,0,99],
+            vec![3,0,3,0,99],
+            vec![109,i64::MAX,109,1,99],
+            vec![109,1,21101,1,1,i64::MAX,99],
+        ].iter() {
+            assert!(same(program, &[7]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_amplifiers() {
+        let amp = [3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+                   27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+        for phase in 5..10 {
+            same(&amp, &[phase, 0, 3, 9, 27, 81]).unwrap();
+        }
+        let program = day(7);
+        for phase in 0..5 {
+            same(&program, &[phase, 17]).unwrap();
+        }
+        // the feedback phases keep asking for signals until they halt
+        for phase in 5..10 {
+            let mut input = vec![phase];
+            input.extend(0..20);
+            same(&program, &input).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_inputs() {
+        let mut program = day(2);
+        program[1] = 12;
+        program[2] = 2;
+        assert_eq!(Ok(3562624), same(&program, &[]));
+        same(&day(5), &[1]).unwrap();
+        same(&day(5), &[5]).unwrap();
+        same(&day(9), &[1]).unwrap();
+        same(&day(9), &[2]).unwrap();
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // counts down from 3, so ten instructions execute before it halts
+        let countdown = vec![3,100,4,100,1001,100,-1,100,1005,100,2,99];
+        for limit in 0..12 {
+            let mut interpreted = IntcodeMachine::with_io(countdown.to_owned(), BufferIo::new(vec![3]));
+            let mut compiled = interpreted.clone();
+            compiled.compile();
+            loop {
+                let reason = interpreted.run_until(Some(limit));
+                assert_eq!(reason, compiled.run_until(Some(limit)));
+                assert_eq!(interpreted.ip(), compiled.ip());
+                if reason != Ok(StopReason::StepLimit) && reason != Ok(StopReason::Output(3)) {
+                    break;
+                }
+                if limit == 0 {
+                    break;
+                }
+            }
+        }
+    }
+}
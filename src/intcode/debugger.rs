@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use super::{BufferIo, IntcodeError, IntcodeMachine, Snapshot, StopReason};
+use super::disasm::decode;
+
+const HELP: &str = "\
+b ADDR          toggle a breakpoint at ADDR
+w ADDR          toggle a watchpoint on the memory cell at ADDR
+s [N]           step N instructions (default 1)
+c               continue until a breakpoint, watchpoint, input wait or halt
+r               print registers
+l [ADDR] [N]    list N instructions from ADDR (default ip, 10)
+x ADDR [N]      dump N memory cells from ADDR (default 8)
+set ADDR VALUE  set the memory cell at ADDR
+in VALUE...     queue input values
+save FILE       save a snapshot of the machine
+load FILE       replace the machine with a saved snapshot
+q               quit
+";
+
+// An interactive debugger around an IntcodeMachine.  Input is queued by
+// hand with `in`, and output is printed as the machine produces it.
+pub struct Debugger {
+    mach: IntcodeMachine<BufferIo>,
+    breakpoints: BTreeSet<usize>,
+    // watched address -> value when last looked at
+    watches: BTreeMap<usize, i64>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<i64>) -> Debugger {
+        Debugger {
+            mach: IntcodeMachine::with_io(program, BufferIo::default()),
+            breakpoints: BTreeSet::new(),
+            watches: BTreeMap::new(),
+        }
+    }
+
+    // Reads commands from `commands` until it runs out or `q` is given.  The
+    // session ends early if a command can't be read or `out` can't be written.
+    pub fn repl<I, W>(&mut self, commands: I, mut out: W) -> Result<(), IntcodeError>
+    where
+        I: BufRead,
+        W: Write,
+    {
+        self.session(commands, &mut out).map_err(|e| IntcodeError::Io(e.to_string()))
+    }
+
+    fn session<I: BufRead, W: Write>(&mut self, commands: I, out: &mut W) -> io::Result<()> {
+        write!(out, "(icdb) ")?;
+        out.flush()?;
+        for line in commands.lines() {
+            if !self.command(&line?, out)? {
+                return Ok(());
+            }
+            write!(out, "(icdb) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    // Carries out one command; false once the user asks to quit.
+    pub fn command<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let nums: Result<Vec<i64>, _> = words.iter().skip(1).map(|w| w.parse::<i64>()).collect();
+        let nums = match nums {
+            Ok(nums) => nums,
+            Err(_) if words.first() == Some(&"save") || words.first() == Some(&"load") => Vec::new(),
+            Err(_) => {
+                writeln!(out, "bad number in {:?}", line)?;
+                return Ok(true);
+            },
+        };
+        // everything but the values given to set and in is an address or a count
+        let unsigned = match words.first() {
+            Some(&"in") => 0,
+            Some(&"set") => 1,
+            _ => nums.len(),
+        };
+        if nums.iter().take(unsigned).any(|n| usize::try_from(*n).is_err()) {
+            writeln!(out, "bad address or count in {:?}", line)?;
+            return Ok(true);
+        }
+        let arg = |i: usize, default: i64| *nums.get(i).unwrap_or(&default);
+
+        match words.as_slice() {
+            [] => {},
+            ["q"] | ["quit"] => return Ok(false),
+            ["h"] | ["help"] => write!(out, "{}", HELP)?,
+            ["b", _] | ["break", _] => {
+                let addr = arg(0, 0) as usize;
+                if !self.breakpoints.remove(&addr) {
+                    self.breakpoints.insert(addr);
+                }
+                let set: Vec<&usize> = self.breakpoints.iter().collect();
+                writeln!(out, "breakpoints: {:?}", set)?;
+            },
+            ["w", _] | ["watch", _] => {
+                let addr = arg(0, 0) as usize;
+                if self.watches.remove(&addr).is_none() {
+                    self.watches.insert(addr, self.mach.peek(addr));
+                }
+                let watched: Vec<&usize> = self.watches.keys().collect();
+                writeln!(out, "watchpoints: {:?}", watched)?;
+            },
+            ["s"] | ["s", _] | ["step"] | ["step", _] => {
+                for _ in 0..arg(0, 1) {
+                    if !self.step(out)? {
+                        break;
+                    }
+                }
+                self.show_ip(out)?;
+            },
+            ["c"] | ["continue"] => {
+                // always get off the breakpoint we may be sitting on
+                if self.step(out)? {
+                    while !self.breakpoints.contains(&self.mach.ip()) && self.step(out)? {}
+                }
+                self.show_ip(out)?;
+            },
+            ["r"] | ["regs"] => {
+                writeln!(out, "ip {}  rb {}  queued input {:?}",
+                         self.mach.ip(),
+                         self.mach.relative_base(),
+                         self.mach.io_mut().input)?;
+            },
+            ["l"] | ["l", ..] | ["list"] | ["list", ..] => {
+                let addr = arg(0, self.mach.ip() as i64) as usize;
+                self.list(addr, arg(1, 10) as usize, out)?;
+            },
+            ["x", ..] | ["dump", ..] if !nums.is_empty() => {
+                let start = arg(0, 0) as usize;
+                let end = start.saturating_add(arg(1, 8) as usize);
+                for row in (start..end).step_by(8) {
+                    let cells: Vec<String> = (row..end.min(row.saturating_add(8)))
+                        .map(|a| self.mach.peek(a).to_string())
+                        .collect();
+                    writeln!(out, "{:04}: {}", row, cells.join(" "))?;
+                }
+            },
+            ["set", _, _] => {
+                self.mach.poke(arg(0, 0) as usize, arg(1, 0));
+                if let Some(seen) = self.watches.get_mut(&(arg(0, 0) as usize)) {
+                    *seen = arg(1, 0);
+                }
+            },
+            ["in", ..] if !nums.is_empty() => {
+                self.mach.io_mut().input.extend(nums.iter());
+            },
+            ["save", path] => {
+                let res = File::create(path)
+                    .and_then(|mut f| write!(f, "{}", self.mach.snapshot()));
+                if let Err(e) = res {
+                    writeln!(out, "{}", e)?;
+                }
+            },
+            ["load", path] => {
+                let snap = fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| Snapshot::read(s.as_bytes()).map_err(|e| e.to_string()));
+                match snap {
+                    Ok(snap) => {
+                        let io = BufferIo::new(snap.input.to_owned());
+                        self.mach = snap.restore(io);
+                        self.show_ip(out)?;
+                    },
+                    Err(e) => writeln!(out, "{}", e)?,
+                }
+            },
+            _ => writeln!(out, "unknown command {:?}; try help", line)?,
+        }
+        Ok(true)
+    }
+
+    // Steps once, reporting anything of note.  Returns false if the machine
+    // could not or should not keep going.
+    fn step<W: Write>(&mut self, out: &mut W) -> io::Result<bool> {
+        let ip = self.mach.ip();
+        let stop = match self.mach.step() {
+            Ok(stop) => stop,
+            Err(e) => {
+                writeln!(out, "{}", e)?;
+                return Ok(false);
+            },
+        };
+        match stop {
+            Some(StopReason::Output(o)) => {
+                self.mach.io_mut().output.clear();
+                writeln!(out, "output: {}", o)?;
+            },
+            Some(StopReason::NeedsInput) => {
+                writeln!(out, "waiting for input")?;
+                return Ok(false);
+            },
+            Some(StopReason::Halted) => {
+                writeln!(out, "halted")?;
+                return Ok(false);
+            },
+            Some(StopReason::StepLimit) | None => {},
+        }
+
+        let mut changed = false;
+        for (addr, seen) in self.watches.iter_mut() {
+            let now = self.mach.peek(*addr);
+            if now != *seen {
+                writeln!(out, "watch {:04}: {} -> {} (by {:04})", addr, seen, now, ip)?;
+                *seen = now;
+                changed = true;
+            }
+        }
+        Ok(!changed)
+    }
+
+    fn show_ip<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let ip = self.mach.ip();
+        self.list(ip, 1, out)
+    }
+
+    fn list<W: Write>(&mut self, mut addr: usize, n: usize, out: &mut W) -> io::Result<()> {
+        for _ in 0..n {
+            let marker = if addr == self.mach.ip() {
+                "=>"
+            } else if self.breakpoints.contains(&addr) {
+                " *"
+            } else {
+                "  "
+            };
+            // the longest instruction is four words
+            let window: Vec<i64> = (addr..addr.saturating_add(4)).map(|a| self.mach.peek(a)).collect();
+            let len = match decode(&window, 0) {
+                Some(instr) => {
+                    writeln!(out, "{} {:04}: {}", marker, addr, instr)?;
+                    instr.len()
+                },
+                None => {
+                    writeln!(out, "{} {:04}: DB {}", marker, addr, window[0])?;
+                    1
+                },
+            };
+            addr = match addr.checked_add(len) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(program: &[i64], commands: &str) -> String {
+        let mut out = Vec::new();
+        Debugger::new(program.to_vec()).repl(commands.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap().replace("(icdb) ", "")
+    }
+
+    const EQ_8: [i64; 11] = [3,9,8,9,10,9,4,9,99,-1,8];
+
+    #[test]
+    fn test_step_and_input() {
+        assert_eq!("\
+waiting for input
+=> 0000: IN [9]
+=> 0002: EQ [9], [10], [9]
+output: 1
+halted
+=> 0008: HLT
+ip 8  rb 0  queued input []
+", session(&EQ_8, "s\nin 8\ns\nc\nr\nq\n"));
+    }
+
+    #[test]
+    fn test_breakpoints_and_watches() {
+        assert_eq!("\
+breakpoints: [6]
+watchpoints: [9]
+watch 0009: -1 -> 5 (by 0000)
+=> 0002: EQ [9], [10], [9]
+watch 0009: 5 -> 0 (by 0002)
+=> 0006: OUT [9]
+output: 0
+halted
+=> 0008: HLT
+", session(&EQ_8, "b 6\nw 9\nin 5\nc\nc\nc\nq\n"));
+    }
+
+    #[test]
+    fn test_memory_commands() {
+        assert_eq!("\
+0008: 99 -1 8
+   0008: HLT
+=> 0000: HLT
+0000: 99 -1 8
+", session(&EQ_8, "x 8 3\nset 0 99\nset 1 -1\nl 8 1\nl 0 1\nx 0 3\nq\n"));
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        assert_eq!("\
+bad number in \"b x\"
+unknown command \"jump 3\"; try help
+bad address or count in \"x -1\"
+bad address or count in \"x 0 -1\"
+bad address or count in \"l 0 -1\"
+bad address or count in \"s -2\"
+", session(&EQ_8, "b x\njump 3\nx -1\nx 0 -1\nl 0 -1\ns -2\nset 9 -5\nin -3\nquit\n"));
+    }
+
+    #[test]
+    fn test_io_errors() {
+        // a command which isn't UTF-8
+        let mut out = Vec::new();
+        let res = Debugger::new(EQ_8.to_vec()).repl(&b"r\n\xff\xfe\nr\n"[..], &mut out);
+        assert!(matches!(res, Err(IntcodeError::Io(_))));
+        assert_eq!(1, String::from_utf8(out).unwrap().matches("ip 0").count());
+
+        // output which fills up after a few bytes, as a closed pipe would
+        let mut full = [0u8; 16];
+        let res = Debugger::new(EQ_8.to_vec()).repl(&b"h\nr\n"[..], &mut full[..]);
+        assert!(matches!(res, Err(IntcodeError::Io(_))));
+    }
+}
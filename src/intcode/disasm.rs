@@ -0,0 +1,219 @@
+use std::fmt::{self, Write};
+use super::Mode;
+use super::access::{Access, AccessMap};
+
+// The instruction set, as the disassembler and assembler see it
+pub struct Op {
+    pub code: i64,
+    pub name: &'static str,
+    pub params: usize,
+    // the last parameter is an address the instruction writes to
+    pub writes: bool,
+}
+
+pub const OPS: [Op; 10] = [
+    Op { code: 1, name: "ADD", params: 3, writes: true },
+    Op { code: 2, name: "MUL", params: 3, writes: true },
+    Op { code: 3, name: "IN", params: 1, writes: true },
+    Op { code: 4, name: "OUT", params: 1, writes: false },
+    Op { code: 5, name: "JT", params: 2, writes: false },
+    Op { code: 6, name: "JF", params: 2, writes: false },
+    Op { code: 7, name: "LT", params: 3, writes: true },
+    Op { code: 8, name: "EQ", params: 3, writes: true },
+    Op { code: 9, name: "ARB", params: 1, writes: false },
+    Op { code: 99, name: "HLT", params: 0, writes: false },
+];
+
+// most data words shown on one line of a listing
+const DATA_PER_LINE: usize = 8;
+
+// An instruction decoded from the program at some address
+#[derive(Clone)]
+pub struct Instruction {
+    pub op: &'static Op,
+    pub args: Vec<(Mode, i64)>,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.op.params + 1
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.op.name)?;
+        for (i, (mode, value)) in self.args.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, format_operand(mode, *value))?;
+        }
+        Ok(())
+    }
+}
+
+// Decodes the instruction at `addr`.  Anything the machine would fault on, or
+// which we couldn't reassemble to the same words, is None: an unknown opcode,
+// a bad or superfluous mode digit, a write in value mode, or an instruction
+// running off the end of the program.
+pub fn decode(program: &[i64], addr: usize) -> Option<Instruction> {
+    let word = program[addr];
+    if word < 0 {
+        return None;
+    }
+    let op = OPS.iter().find(|op| op.code == word % 100)?;
+    if addr + op.params >= program.len() {
+        return None;
+    }
+    let mut modes = word / 100;
+    let mut args = Vec::new();
+    for i in 0..op.params {
+        let mode = match modes % 10 {
+            0 => Mode::Pointer,
+            1 => Mode::Value,
+            2 => Mode::Relative,
+            _ => return None,
+        };
+        if op.writes && i == op.params - 1 && mode == Mode::Value {
+            return None;
+        }
+        args.push((mode, program[addr + i + 1]));
+        modes /= 10;
+    }
+    if modes != 0 {
+        return None;
+    }
+    Some(Instruction { op, args })
+}
+
+fn format_operand(mode: &Mode, value: i64) -> String {
+    match mode {
+        Mode::Pointer => format!("[{}]", value),
+        Mode::Value => format!("#{}", value),
+        Mode::Relative if value < 0 => format!("[rb{}]", value),
+        Mode::Relative => format!("[rb+{}]", value),
+    }
+}
+
+// Produces a listing of the program, one instruction per line, e.g.
+//
+//   0012: ADD [rb+3], #5, [104]
+//
+// Words which don't decode as instructions are shown as DB data lines.
+pub fn disassemble(program: &[i64]) -> String {
+    listing(program, None)
+}
+
+// As disassemble, but guided by how a run of the program used its memory.
+// Cells the run used only as data are listed as data even if they decode,
+// and so are the operands of instructions it never ran.  Instructions which
+// the run wrote over are marked.  Anything the run didn't touch is guessed
+// at, as disassemble does.
+pub fn disassemble_map(program: &[i64], map: &AccessMap) -> String {
+    listing(program, Some(map))
+}
+
+fn listing(program: &[i64], map: Option<&AccessMap>) -> String {
+    let mut listing = String::new();
+    let mut data: Vec<i64> = Vec::new();
+    let mut data_addr = 0;
+    let mut addr = 0;
+    while addr < program.len() {
+        // an instruction the run didn't execute mustn't take in any cells
+        // it used
+        let code = |instr: &Instruction| match map {
+            Some(map) => map.start(addr) || (addr..addr + instr.len()).all(|a| map.get(a) == Access::default()),
+            None => true,
+        };
+        match decode(program, addr).filter(code) {
+            Some(instr) => {
+                flush_data(&mut listing, data_addr, &mut data);
+                let modified = map.is_some_and(|map| {
+                    (addr..addr + instr.len()).any(|a| map.get(a).executed && map.get(a).written)
+                });
+                if modified {
+                    writeln!(listing, "{:04}: {:<30} ; modified", addr, instr.to_string()).unwrap();
+                } else {
+                    writeln!(listing, "{:04}: {}", addr, instr).unwrap();
+                }
+                addr += instr.len();
+            },
+            None => {
+                if data.len() == DATA_PER_LINE {
+                    flush_data(&mut listing, data_addr, &mut data);
+                }
+                if data.is_empty() {
+                    data_addr = addr;
+                }
+                data.push(program[addr]);
+                addr += 1;
+            },
+        }
+    }
+    flush_data(&mut listing, data_addr, &mut data);
+    listing
+}
+
+fn flush_data(listing: &mut String, addr: usize, data: &mut Vec<i64>) {
+    if data.is_empty() {
+        return;
+    }
+    let words: Vec<String> = data.iter().map(|d| d.to_string()).collect();
+    writeln!(listing, "{:04}: DB {}", addr, words.join(", ")).unwrap();
+    data.clear();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::access::analyse;
+
+    #[test]
+    fn test_disassemble() {
+        let eq_8_ptr: Vec<i64> = [3,9,8,9,10,9,4,9,99,-1,8].to_vec();
+        assert_eq!("\
+0000: IN [9]
+0002: EQ [9], [10], [9]
+0006: OUT [9]
+0008: HLT
+0009: DB -1, 8
+", disassemble(&eq_8_ptr));
+
+        let quine: Vec<i64> = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99].to_vec();
+        assert_eq!("\
+0000: ARB #1
+0002: OUT [rb-1]
+0004: ADD [100], #1, [100]
+0008: EQ [100], #16, [101]
+0012: JF [101], #0
+0015: HLT
+", disassemble(&quine));
+    }
+
+    #[test]
+    fn test_undecodable_words_are_data() {
+        // value mode write, bad mode digit, superfluous mode digit, truncated
+        let junk: Vec<i64> = [11101,-1,-1,-1,301,-1,-1,-1,1099,22201,0,0,0,1,0,0].to_vec();
+        assert_eq!("\
+0000: DB 11101, -1, -1, -1, 301, -1, -1, -1
+0008: DB 1099
+0009: ADD [rb+0], [rb+0], [rb+0]
+0013: DB 1, 0, 0
+", disassemble(&junk));
+    }
+
+    #[test]
+    fn test_disassemble_map() {
+        // the cells after HLT are data, but would decode as an ADD
+        let program: Vec<i64> = [1,10,11,3,2,3,12,0,99,1,1,1,1,0].to_vec();
+        assert!(disassemble(&program).contains("0009: ADD [1], [1], [1]\n"));
+
+        let map = analyse(program.to_owned(), vec![]).unwrap();
+        assert_eq!("\
+0000: ADD [10], [11], [3]            ; modified
+0004: MUL [3], [12], [0]
+0008: HLT
+0009: DB 1, 1, 1, 1, 0
+", disassemble_map(&program, &map));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Sender, Receiver, TryRecvError};
+use super::Fault;
+
+// Where an IntcodeMachine gets its input and sends its output.
+pub trait IntcodeIo {
+    // Returns the next input, or None if nothing is available yet.
+    fn read_input(&mut self) -> Result<Option<i64>, Fault>;
+
+    // Returns the next input, blocking until one arrives if the source can.
+    // Sources which cannot block treat running dry as being closed.
+    fn wait_input(&mut self) -> Result<i64, Fault> {
+        self.read_input()?.ok_or(Fault::InputClosed)
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault>;
+
+    // Input queued but not yet read, and output written but not yet
+    // collected, for sources which hold on to them.  Used by snapshots.
+    fn pending_input(&self) -> Vec<i64> {
+        Vec::new()
+    }
+
+    fn pending_output(&self) -> Vec<i64> {
+        Vec::new()
+    }
+}
+
+// The machine's side of a pair of mpsc channels, for machines run on their
+// own thread.
+pub struct ChannelIo {
+    pub tx: Sender<i64>,
+    pub rx: Receiver<i64>,
+}
+
+impl IntcodeIo for ChannelIo {
+    fn read_input(&mut self) -> Result<Option<i64>, Fault> {
+        match self.rx.try_recv() {
+            Ok(inp) => Ok(Some(inp)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Fault::InputClosed),
+        }
+    }
+
+    fn wait_input(&mut self) -> Result<i64, Fault> {
+        self.rx.recv().map_err(|_| Fault::InputClosed)
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault> {
+        self.tx.send(value).map_err(|_| Fault::OutputClosed)
+    }
+}
+
+// In-memory queues; the caller pushes input and drains output between runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BufferIo {
+    pub input: VecDeque<i64>,
+    pub output: VecDeque<i64>,
+}
+
+impl BufferIo {
+    pub fn new(input: Vec<i64>) -> BufferIo {
+        BufferIo {
+            input: input.into(),
+            output: VecDeque::new(),
+        }
+    }
+}
+
+impl IntcodeIo for BufferIo {
+    fn read_input(&mut self) -> Result<Option<i64>, Fault> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault> {
+        self.output.push_back(value);
+        Ok(())
+    }
+
+    fn pending_input(&self) -> Vec<i64> {
+        self.input.iter().cloned().collect()
+    }
+
+    fn pending_output(&self) -> Vec<i64> {
+        self.output.iter().cloned().collect()
+    }
+}
+
+// Input and output through a pair of closures.
+pub struct FnIo<I, O> {
+    input: I,
+    output: O,
+}
+
+impl<I, O> FnIo<I, O>
+where
+    I: FnMut() -> Option<i64>,
+    O: FnMut(i64),
+{
+    pub fn new(input: I, output: O) -> FnIo<I, O> {
+        FnIo { input, output }
+    }
+}
+
+impl<I, O> IntcodeIo for FnIo<I, O>
+where
+    I: FnMut() -> Option<i64>,
+    O: FnMut(i64),
+{
+    fn read_input(&mut self) -> Result<Option<i64>, Fault> {
+        Ok((self.input)())
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault> {
+        (self.output)(value);
+        Ok(())
+    }
+}
+
+// Text streams: each input is one byte from `reader`, each output is written
+// to `writer` as a character.  Values outside of ASCII can't be shown as a
+// character, so they are written out in decimal, followed by a newline.
+pub struct AsciiIo<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> AsciiIo<R, W> {
+    pub fn new(reader: R, writer: W) -> AsciiIo<R, W> {
+        AsciiIo { reader, writer }
+    }
+}
+
+impl<R: Read, W: Write> IntcodeIo for AsciiIo<R, W> {
+    fn read_input(&mut self) -> Result<Option<i64>, Fault> {
+        let mut byte = [0u8; 1];
+        match self.reader.read(&mut byte) {
+            Ok(1) => Ok(Some(byte[0] as i64)),
+            _ => Err(Fault::InputClosed),
+        }
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault> {
+        let res = if (0..128).contains(&value) {
+            self.writer.write_all(&[value as u8])
+        } else {
+            writeln!(self.writer, "{}", value)
+        };
+        res.and_then(|_| self.writer.flush())
+           .map_err(|_| Fault::OutputClosed)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{IntcodeMachine, StopReason};
+
+    #[test]
+    fn test_buffer_io() {
+        let lt_8_ptr: Vec<i64> = [3,9,7,9,10,9,4,9,99,-1,8].to_vec();
+        let mut mach = IntcodeMachine::with_io(lt_8_ptr.to_owned(), BufferIo::new(vec![7]));
+        mach.run_program().unwrap();
+        assert_eq!(vec![1], Vec::from(mach.io_mut().output.to_owned()));
+
+        let mut mach = IntcodeMachine::with_io(lt_8_ptr, BufferIo::default());
+        assert_eq!(Ok(StopReason::NeedsInput), mach.run_until(None));
+        mach.io_mut().input.push_back(9);
+        assert_eq!(Ok(StopReason::Output(0)), mach.run_until(None));
+        assert_eq!(Some(0), mach.io_mut().output.pop_front());
+        assert_eq!(Ok(StopReason::Halted), mach.run_until(None));
+    }
+
+    #[test]
+    fn test_buffer_io_runs_dry() {
+        let two_inputs: Vec<i64> = [3,0,3,0,99].to_vec();
+        let mut mach = IntcodeMachine::with_io(two_inputs, BufferIo::new(vec![1]));
+        assert!(mach.run_program().is_err());
+    }
+
+    #[test]
+    fn test_fn_io() {
+        let quine: Vec<i64> = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99].to_vec();
+        let mut out = Vec::new();
+        {
+            let io = FnIo::new(|| None, |o| out.push(o));
+            let mut mach = IntcodeMachine::with_io(quine.to_owned(), io);
+            mach.run_program().unwrap();
+        }
+        assert_eq!(quine, out);
+    }
+
+    #[test]
+    fn test_ascii_io() {
+        // echoes three bytes back, then outputs a large number
+        let echo: Vec<i64> = [3,0,4,0,3,0,4,0,3,0,4,0,104,1000,99].to_vec();
+        let mut out = Vec::new();
+        {
+            let io = AsciiIo::new(&b"hi\n"[..], &mut out);
+            let mut mach = IntcodeMachine::with_io(echo, io);
+            mach.run_program().unwrap();
+        }
+        assert_eq!("hi\n1000\n", String::from_utf8(out).unwrap());
+    }
+}
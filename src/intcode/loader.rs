@@ -0,0 +1,226 @@
+use std::fs;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+use super::IntcodeError;
+
+// Binary programs start with this, followed by a byte naming the encoding.
+const MAGIC: &[u8] = b"\0ICP";
+
+// How a program is stored in binary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    // each word as 8 little endian bytes
+    Words,
+    // each word zigzagged, so that small negatives stay small, and then
+    // written 7 bits to a byte, low bits first, with the top bit set on all
+    // but the last byte
+    Varint,
+}
+
+impl Encoding {
+    fn tag(self) -> u8 {
+        match self {
+            Encoding::Words => b'q',
+            Encoding::Varint => b'v',
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s {
+            "words" => Ok(Encoding::Words),
+            "varint" => Ok(Encoding::Varint),
+            _ => Err(format!("unknown encoding {:?}", s)),
+        }
+    }
+}
+
+// Reads a whole program, either as text or in one of the binary encodings.
+//
+// As text, the numbers may be split over any number of lines and separated by
+// commas, whitespace or both.  A trailing comma is allowed, and # starts a
+// comment which runs to the end of the line.
+pub fn read_program<I>(mut buf: I) -> Result<Vec<i64>, IntcodeError>
+where
+    I: BufRead,
+{
+    let mut bytes = Vec::new();
+    buf.read_to_end(&mut bytes)
+       .map_err(|e| IntcodeError::Io(e.to_string()))?;
+    parse_program(&bytes)
+}
+
+pub fn load_program<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, IntcodeError> {
+    let bytes = fs::read(path.as_ref())
+        .map_err(|e| IntcodeError::Io(format!("{}: {}", path.as_ref().display(), e)))?;
+    parse_program(&bytes)
+}
+
+fn parse_program(bytes: &[u8]) -> Result<Vec<i64>, IntcodeError> {
+    if bytes.starts_with(MAGIC) {
+        return decode(&bytes[MAGIC.len()..]);
+    }
+    let text = std::str::from_utf8(bytes).map_err(|e| IntcodeError::Io(e.to_string()))?;
+    parse_text(text)
+}
+
+fn parse_text(text: &str) -> Result<Vec<i64>, IntcodeError> {
+    let mut program = Vec::new();
+    // (line, column) of a comma with no number after it yet; another one
+    // before the next number is a mistake, as is one before the first
+    // number, but one may be the last thing in the program
+    let mut comma: Option<(usize, usize)> = None;
+    for (row, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        // (column, byte offset) where the number being read began
+        let mut start = None;
+        let ends = line.char_indices().chain(Some((line.len(), ' ')));
+        for (col, (at, c)) in ends.enumerate() {
+            if c != ',' && !c.is_whitespace() {
+                start = start.or(Some((col, at)));
+                continue;
+            }
+            if let Some((s, from)) = start.take() {
+                let token = &line[from..at];
+                let value = token.parse().map_err(|_| IntcodeError::Parse {
+                    line: row + 1,
+                    column: s + 1,
+                    token: token.to_string(),
+                })?;
+                program.push(value);
+                comma = None;
+            }
+            if c == ',' {
+                let stray = match comma {
+                    Some(prev) => Some(prev),
+                    None if program.is_empty() => Some((row, col)),
+                    None => None,
+                };
+                if let Some((line, column)) = stray {
+                    return Err(IntcodeError::Parse { line: line + 1, column: column + 1, token: ",".to_string() });
+                }
+                comma = Some((row, col));
+            }
+        }
+    }
+    Ok(program)
+}
+
+// Encodes a program in binary, in a form read_program and load_program read.
+pub fn encode_program(program: &[i64], encoding: Encoding) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(encoding.tag());
+    for word in program {
+        match encoding {
+            Encoding::Words => bytes.extend_from_slice(&word.to_le_bytes()),
+            Encoding::Varint => {
+                let mut zigzag = ((word << 1) ^ (word >> 63)) as u64;
+                while zigzag >= 0x80 {
+                    bytes.push(zigzag as u8 | 0x80);
+                    zigzag >>= 7;
+                }
+                bytes.push(zigzag as u8);
+            },
+        }
+    }
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<i64>, IntcodeError> {
+    let truncated = || IntcodeError::Io("binary program is truncated".to_string());
+    let (tag, body) = bytes.split_first().ok_or_else(truncated)?;
+    let mut program = Vec::new();
+    match *tag {
+        b'q' => {
+            if body.len() % 8 != 0 {
+                return Err(truncated());
+            }
+            for word in body.chunks(8) {
+                let mut le = [0; 8];
+                le.copy_from_slice(word);
+                program.push(i64::from_le_bytes(le));
+            }
+        },
+        b'v' => {
+            let mut zigzag: u64 = 0;
+            let mut shift = 0;
+            for b in body {
+                if shift > 63 {
+                    return Err(IntcodeError::Io("varint too long".to_string()));
+                }
+                zigzag |= ((b & 0x7f) as u64) << shift;
+                shift += 7;
+                if b & 0x80 == 0 {
+                    program.push((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+                    zigzag = 0;
+                    shift = 0;
+                }
+            }
+            if shift != 0 {
+                return Err(truncated());
+            }
+        },
+        t => return Err(IntcodeError::Io(format!("unknown binary encoding {:?}", t as char))),
+    }
+    Ok(program)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_text() {
+        assert_eq!(Ok(vec![1,0,-2,99]), read_program(&b"1,0,-2,99\n"[..]));
+        assert_eq!(Ok(vec![1,0,-2,99]), read_program(&b"1, 0,\n -2,\t99,\n\n"[..]));
+        assert_eq!(Ok(vec![1,0,-2,99]), read_program(&b"# add\n1,0,-2 # into -2\n99 # done\n"[..]));
+        assert_eq!(Ok(vec![1,2]), read_program(&b"1 2"[..]));
+        assert_eq!(Ok(vec![]), read_program(&b""[..]));
+    }
+
+    #[test]
+    fn test_text_errors() {
+        assert_eq!(Err(IntcodeError::Parse { line: 1, column: 5, token: "x".to_string() }),
+                   read_program(&b"1,0,x,99\n"[..]));
+        assert_eq!(Err(IntcodeError::Parse { line: 2, column: 3, token: "9x9".to_string() }),
+                   read_program(&b"1,0,\n  9x9,1\n"[..]));
+        assert_eq!(Err(IntcodeError::Parse { line: 1, column: 3, token: ",".to_string() }),
+                   read_program(&b"1 ,, 2"[..]));
+        // a comma before the first number, or two either side of a line break
+        assert_eq!(Err(IntcodeError::Parse { line: 2, column: 2, token: ",".to_string() }),
+                   read_program(&b"# leading
+ ,1,2
+"[..]));
+        assert_eq!(Err(IntcodeError::Parse { line: 1, column: 4, token: ",".to_string() }),
+                   read_program(&b"1,2,
+,3
+"[..]));
+        assert_eq!("error parsing number \"x\" at line 1, column 5",
+                   read_program(&b"1,0,x,99"[..]).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_binary() {
+        let program = vec![1, 0, -2, 99, 63, -64, 64, i64::MAX, i64::MIN];
+        for encoding in [Encoding::Words, Encoding::Varint].iter() {
+            let bytes = encode_program(&program, *encoding);
+            assert_eq!(Ok(program.to_owned()), read_program(&bytes[..]), "{:?}", encoding);
+            assert!(read_program(&bytes[..bytes.len() - 1]).is_err());
+        }
+        // small words take a byte each as varints
+        assert_eq!(MAGIC.len() + 1 + 4, encode_program(&[1, 0, -2, 63], Encoding::Varint).len());
+        assert!(read_program(&b"\0ICPz"[..]).is_err());
+    }
+
+    #[test]
+    fn test_load_program() {
+        let program = read_program(&include_bytes!("../../9/input")[..]).unwrap();
+        assert_eq!(Ok(program), load_program("9/input"));
+        assert!(load_program("no/such/program").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+// How far past the end of dense memory a write may land and still grow it;
+// anything further out is kept in the sparse map instead.
+const DENSE_SLACK: usize = 0x1000;
+
+// Machine memory which grows on demand.  Cells near the program are kept in a
+// Vec; writes to far off addresses go to a map so that a single write to a
+// huge address doesn't allocate everything in between.  Cells that were never
+// written read as 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Memory {
+    dense: Vec<i64>,
+    sparse: BTreeMap<usize, i64>,
+    // values too big for a cell, which only widened arithmetic makes; the
+    // cell itself keeps the low 64 bits
+    wide: BTreeMap<usize, i128>,
+}
+
+impl Memory {
+    pub fn new(program: Vec<i64>) -> Memory {
+        Memory {
+            dense: program,
+            sparse: BTreeMap::new(),
+            wide: BTreeMap::new(),
+        }
+    }
+
+    // cells from address 0 up to the end of dense memory
+    pub fn dense(&self) -> &[i64] {
+        &self.dense
+    }
+
+    // (address, value) of each far off cell, in address order
+    pub fn sparse(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        self.sparse.iter().map(|(a, v)| (*a, *v))
+    }
+
+    pub fn get(&self, addr: usize) -> i64 {
+        match self.dense.get(addr) {
+            Some(v) => *v,
+            None => *self.sparse.get(&addr).unwrap_or(&0),
+        }
+    }
+
+    // (address, value) of each cell holding a value too big for it
+    pub fn wide(&self) -> impl Iterator<Item = (usize, i128)> + '_ {
+        self.wide.iter().map(|(a, v)| (*a, *v))
+    }
+
+    pub fn is_wide(&self, addr: usize) -> bool {
+        !self.wide.is_empty() && self.wide.contains_key(&addr)
+    }
+
+    pub fn get_wide(&self, addr: usize) -> i128 {
+        if self.wide.is_empty() {
+            return self.get(addr) as i128;
+        }
+        match self.wide.get(&addr) {
+            Some(v) => *v,
+            None => self.get(addr) as i128,
+        }
+    }
+
+    pub fn set_wide(&mut self, addr: usize, value: i128) {
+        match i64::try_from(value) {
+            Ok(v) => self.set(addr, v),
+            Err(_) => {
+                self.set(addr, value as i64);
+                self.wide.insert(addr, value);
+            },
+        }
+    }
+
+    pub fn set(&mut self, addr: usize, value: i64) {
+        if !self.wide.is_empty() {
+            self.wide.remove(&addr);
+        }
+        if addr < self.dense.len() {
+            self.dense[addr] = value;
+        } else if addr - self.dense.len() < DENSE_SLACK {
+            self.grow(addr + 1);
+            self.dense[addr] = value;
+        } else {
+            self.sparse.insert(addr, value);
+        }
+    }
+
+    // extends dense memory to `len` cells, pulling in any sparse cells it covers
+    fn grow(&mut self, len: usize) {
+        let start = self.dense.len();
+        self.dense.resize(len, 0);
+        let moved: Vec<usize> = self.sparse.range(start..len).map(|(a, _)| *a).collect();
+        for addr in moved {
+            self.dense[addr] = self.sparse.remove(&addr).unwrap();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grow_on_demand() {
+        let mut mem = Memory::new(vec![1, 2, 3]);
+        assert_eq!(3, mem.get(2));
+        assert_eq!(0, mem.get(3));
+        assert_eq!(0, mem.get(1 << 40));
+
+        mem.set(10, 7);
+        assert_eq!(11, mem.dense.len());
+        assert_eq!(7, mem.get(10));
+        assert_eq!(0, mem.get(9));
+
+        mem.set(1 << 40, 5);
+        assert_eq!(11, mem.dense.len());
+        assert_eq!(5, mem.get(1 << 40));
+    }
+
+    #[test]
+    fn test_sparse_cells_move_into_dense() {
+        let mut mem = Memory::new(vec![0; 4]);
+        mem.set(DENSE_SLACK + 10, 42);
+        assert_eq!(1, mem.sparse.len());
+
+        // creep dense memory up until it covers the sparse cell
+        mem.set(DENSE_SLACK / 2, 1);
+        assert_eq!(1, mem.sparse.len());
+        mem.set(DENSE_SLACK + 20, 1);
+        assert!(mem.sparse.is_empty());
+        assert_eq!(DENSE_SLACK + 21, mem.dense.len());
+        assert_eq!(42, mem.get(DENSE_SLACK + 10));
+    }
+
+    #[test]
+    fn test_wide_cells() {
+        let mut mem = Memory::new(vec![0; 4]);
+        let big = i64::MAX as i128 * 4;
+        mem.set_wide(2, big);
+        assert!(mem.is_wide(2));
+        assert_eq!(big, mem.get_wide(2));
+        assert_eq!(big as i64, mem.get(2));
+
+        // coming back into range, or an ordinary write, narrows the cell
+        mem.set_wide(2, 5);
+        assert!(!mem.is_wide(2));
+        mem.set_wide(3, big);
+        mem.set(3, 6);
+        assert_eq!(6, mem.get_wide(3));
+        assert_eq!(0, mem.wide().count());
+    }
+}
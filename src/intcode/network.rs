@@ -0,0 +1,169 @@
+use super::{BufferIo, IntcodeError, IntcodeMachine, StopReason};
+
+// How a network's run ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    // every machine halted
+    Halted,
+    // these machines are all waiting for input, and nothing is left to send any
+    Deadlock(Vec<usize>),
+}
+
+// most instructions a machine runs before the next one gets a turn
+const QUANTUM: usize = 10_000;
+
+// A group of machines run together on one thread.  Each machine's output is
+// copied to the input of every machine it is connected to; machines are run
+// in turn until all have halted or none can make progress.
+#[derive(Default)]
+pub struct Network {
+    machines: Vec<IntcodeMachine<BufferIo>>,
+    // machine -> machines its output goes to
+    links: Vec<Vec<usize>>,
+    // everything each machine has output, in order
+    outputs: Vec<Vec<i64>>,
+    halted: Vec<bool>,
+}
+
+impl Network {
+    // Adds a machine running `program`, returning its index in the network.
+    pub fn add(&mut self, program: Vec<i64>) -> usize {
+        self.machines.push(IntcodeMachine::with_io(program, BufferIo::default()));
+        self.links.push(Vec::new());
+        self.outputs.push(Vec::new());
+        self.halted.push(false);
+        self.machines.len() - 1
+    }
+
+    // Sends the output of machine `from` to the input of machine `to`.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.links[from].push(to);
+    }
+
+    // Queues input for a machine.
+    pub fn input(&mut self, to: usize, value: i64) {
+        self.machines[to].io_mut().input.push_back(value);
+    }
+
+    // Everything machine `i` has output so far.
+    pub fn outputs(&self, i: usize) -> &[i64] {
+        &self.outputs[i]
+    }
+
+    // Runs the machines round robin until every one has halted, or until all
+    // that remain are waiting on empty inputs.
+    pub fn run(&mut self) -> Result<Outcome, IntcodeError> {
+        loop {
+            let mut progress = false;
+            for i in 0..self.machines.len() {
+                if !self.halted[i] {
+                    progress |= self.turn(i)?;
+                }
+            }
+            if !progress {
+                let waiting: Vec<usize> = (0..self.machines.len())
+                    .filter(|i| !self.halted[*i])
+                    .collect();
+                return Ok(match waiting.len() {
+                    0 => Outcome::Halted,
+                    _ => Outcome::Deadlock(waiting),
+                });
+            }
+        }
+    }
+
+    // Gives machine `i` a turn, delivering its output as it goes.  Returns
+    // whether it did anything.
+    fn turn(&mut self, i: usize) -> Result<bool, IntcodeError> {
+        for steps in 0..QUANTUM {
+            match self.machines[i].step()? {
+                None => {},
+                Some(StopReason::Output(value)) => {
+                    self.machines[i].io_mut().output.clear();
+                    self.outputs[i].push(value);
+                    for j in 0..self.links[i].len() {
+                        let to = self.links[i][j];
+                        self.input(to, value);
+                    }
+                },
+                Some(StopReason::Halted) => {
+                    self.halted[i] = true;
+                    return Ok(true);
+                },
+                Some(StopReason::NeedsInput) | Some(StopReason::StepLimit) => {
+                    return Ok(steps > 0);
+                },
+            }
+        }
+        Ok(true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        // each machine outputs its input plus one, twice
+        let add_one: Vec<i64> = [3,11,1001,11,1,11,4,11,1105,1,0,0].to_vec();
+        let mut net = Network::default();
+        let a = net.add(add_one.to_owned());
+        let b = net.add(add_one.to_owned());
+        let c = net.add(add_one);
+        net.connect(a, b);
+        net.connect(b, c);
+        net.input(a, 10);
+        net.input(a, 20);
+        assert_eq!(Ok(Outcome::Deadlock(vec![a, b, c])), net.run());
+        assert_eq!(&[11,21], net.outputs(a));
+        assert_eq!(&[12,22], net.outputs(b));
+        assert_eq!(&[13,23], net.outputs(c));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let echo: Vec<i64> = [3,7,4,7,1105,1,0,0].to_vec();
+        let mut net = Network::default();
+        let a = net.add(echo.to_owned());
+        let b = net.add(echo.to_owned());
+        let c = net.add(echo);
+        net.connect(c, a);
+        net.connect(c, b);
+        net.input(c, 5);
+        assert_eq!(Ok(Outcome::Deadlock(vec![a, b, c])), net.run());
+        assert_eq!(&[5], net.outputs(a));
+        assert_eq!(&[5], net.outputs(b));
+    }
+
+    #[test]
+    fn test_halted_machines_stop() {
+        // echoes its input, halting after a 1
+        let until_one: Vec<i64> = [3,13,4,13,1001,13,-1,13,1005,13,0,99,0,0].to_vec();
+        let mut net = Network::default();
+        let a = net.add(until_one.to_owned());
+        let b = net.add(until_one);
+        net.connect(a, b);
+        net.connect(b, a);
+        net.input(a, 3);
+        net.input(a, 1);
+        net.input(b, 1);
+        assert_eq!(Ok(Outcome::Halted), net.run());
+        assert_eq!(&[3,1], net.outputs(a));
+        assert_eq!(&[1], net.outputs(b));
+    }
+
+    #[test]
+    fn test_deadlock_spares_halted() {
+        let until_one: Vec<i64> = [3,13,4,13,1001,13,-1,13,1005,13,0,99,0,0].to_vec();
+        let echo: Vec<i64> = [3,7,4,7,1105,1,0,0].to_vec();
+        let mut net = Network::default();
+        let a = net.add(until_one);
+        let b = net.add(echo);
+        net.connect(a, b);
+        net.input(a, 1);
+        assert_eq!(Ok(Outcome::Deadlock(vec![b])), net.run());
+        assert_eq!(&[1], net.outputs(b));
+    }
+}
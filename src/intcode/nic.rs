@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use super::{Fault, IntcodeError, IntcodeIo, IntcodeMachine, StopReason};
+
+// the address which packets for the NAT are sent to
+pub const NAT: i64 = 255;
+
+// most instructions a NIC runs before the next one gets a turn
+const QUANTUM: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet {
+    pub x: i64,
+    pub y: i64,
+}
+
+// Something the NAT saw or did
+#[derive(Debug, Clone, PartialEq)]
+pub enum NatEvent {
+    // a packet was sent to the NAT
+    Received(Packet),
+    // the network went idle, so the NAT sent its last packet to address 0
+    Wake(Packet),
+    // the network went idle before the NAT had anything to send, so these
+    // NICs will wait for ever
+    Deadlock(Vec<usize>),
+}
+
+// A NIC's packet queue.  Reading from an empty queue gives -1 instead of
+// blocking, and marks the NIC as idle until it next reads or sends something.
+#[derive(Default)]
+struct PacketIo {
+    queue: VecDeque<i64>,
+    // output collected until a whole (destination, X, Y) triple is ready
+    sending: Vec<i64>,
+    idle: bool,
+}
+
+impl IntcodeIo for PacketIo {
+    fn read_input(&mut self) -> Result<Option<i64>, Fault> {
+        match self.queue.pop_front() {
+            Some(v) => {
+                self.idle = false;
+                Ok(Some(v))
+            },
+            None => {
+                self.idle = true;
+                Ok(Some(-1))
+            },
+        }
+    }
+
+    fn write_output(&mut self, value: i64) -> Result<(), Fault> {
+        self.idle = false;
+        self.sending.push(value);
+        Ok(())
+    }
+}
+
+// Machines which talk to each other in packets.  Each NIC is first given its
+// address, then sends packets as (destination, X, Y) output triples.  Packets
+// for address 255 go to the NAT, which holds on to the latest one and, when
+// the whole network is idle, sends it on to address 0.
+pub struct PacketNetwork {
+    nics: Vec<IntcodeMachine<PacketIo>>,
+    halted: Vec<bool>,
+    nat: Option<Packet>,
+    // what the NAT has seen but the caller has not yet been told about
+    events: VecDeque<NatEvent>,
+}
+
+impl PacketNetwork {
+    // Boots `n` NICs running `program`, at addresses 0 to n-1.
+    pub fn new(program: Vec<i64>, n: usize) -> PacketNetwork {
+        let nics = (0..n).map(|addr| {
+            let mut io = PacketIo::default();
+            io.queue.push_back(addr as i64);
+            IntcodeMachine::with_io(program.to_owned(), io)
+        }).collect();
+        PacketNetwork {
+            nics,
+            halted: vec![false; n],
+            nat: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    // Runs the network until the NAT next receives or sends a packet.  If the
+    // network goes idle before the NAT has anything to send, nothing ever
+    // will happen again, and that is reported as a deadlock, as Network does.
+    pub fn next_event(&mut self) -> Result<NatEvent, IntcodeError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let mut sent = false;
+            for i in 0..self.nics.len() {
+                if !self.halted[i] {
+                    sent |= self.turn(i)?;
+                }
+            }
+            if sent || !self.idle() {
+                continue;
+            }
+            match self.nat {
+                Some(packet) => {
+                    self.deliver(0, packet);
+                    self.events.push_back(NatEvent::Wake(packet));
+                },
+                None => {
+                    let waiting = (0..self.nics.len()).filter(|i| !self.halted[*i]).collect();
+                    return Ok(NatEvent::Deadlock(waiting));
+                },
+            }
+        }
+    }
+
+    // every NIC has read from an empty queue since it last did anything else
+    fn idle(&self) -> bool {
+        self.nics.iter().zip(self.halted.iter()).all(|(nic, halted)| {
+            let io = nic.io();
+            *halted || (io.idle && io.queue.is_empty() && io.sending.is_empty())
+        })
+    }
+
+    // Gives NIC `i` a turn, until it finds its queue empty.  Returns whether
+    // it sent any packets.
+    fn turn(&mut self, i: usize) -> Result<bool, IntcodeError> {
+        let mut sent = false;
+        for _ in 0..QUANTUM {
+            match self.nics[i].step()? {
+                Some(StopReason::Halted) => {
+                    self.halted[i] = true;
+                    break;
+                },
+                _ if self.nics[i].io().idle => break,
+                _ => {},
+            }
+            let io = self.nics[i].io_mut();
+            if io.sending.len() == 3 {
+                let (dest, x, y) = (io.sending[0], io.sending[1], io.sending[2]);
+                io.sending.clear();
+                self.route(dest, Packet { x, y });
+                sent = true;
+            }
+        }
+        Ok(sent)
+    }
+
+    fn route(&mut self, dest: i64, packet: Packet) {
+        if dest == NAT {
+            self.nat = Some(packet);
+            self.events.push_back(NatEvent::Received(packet));
+        } else if dest >= 0 && (dest as usize) < self.nics.len() {
+            self.deliver(dest as usize, packet);
+        }
+        // like any network, packets for nobody are dropped
+    }
+
+    fn deliver(&mut self, addr: usize, packet: Packet) {
+        let io = self.nics[addr].io_mut();
+        io.queue.push_back(packet.x);
+        io.queue.push_back(packet.y);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assemble;
+
+    // NIC 0 starts by sending (5, 6) to NIC 1.  Every NIC passes each packet
+    // it receives on to the NAT, with its own address added to Y.
+    const RELAY: &str = "
+            in [addr]
+            jt [addr], #loop
+            out #1
+            out #5
+            out #6
+    loop:   in [x]
+            eq [x], #-1, [t]
+            jt [t], #loop
+            in [y]
+            add [y], [addr], [y]
+            out #255
+            out [x]
+            out [y]
+            jt #1, #loop
+    addr:   db 0
+    x:      db 0
+    y:      db 0
+    t:      db 0
+    ";
+
+    #[test]
+    fn test_nat() {
+        let mut net = PacketNetwork::new(assemble(RELAY).unwrap(), 2);
+        let packet = |x, y| Packet { x, y };
+        assert_eq!(Ok(NatEvent::Received(packet(5, 7))), net.next_event());
+        assert_eq!(Ok(NatEvent::Wake(packet(5, 7))), net.next_event());
+        // NIC 0 adds nothing, so the same packet comes round for ever
+        assert_eq!(Ok(NatEvent::Received(packet(5, 7))), net.next_event());
+        assert_eq!(Ok(NatEvent::Wake(packet(5, 7))), net.next_event());
+    }
+
+    #[test]
+    fn test_idle_without_nat() {
+        // the packet to NIC 1 goes nowhere when there is only NIC 0
+        let mut net = PacketNetwork::new(assemble(RELAY).unwrap(), 1);
+        assert_eq!(Ok(NatEvent::Deadlock(vec![0])), net.next_event());
+    }
+}
@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use super::{BufferIo, IntcodeMachine, StopReason};
+
+// most instructions one run may take before it is given up on
+const RUN_LIMIT: usize = 10_000_000;
+// most values of input an Input slot can be after, as every run's input is
+// filled out to reach it
+const INPUT_LIMIT: usize = 1 << 16;
+
+// Something a search varies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+    // a memory cell, set before the program starts
+    Cell(usize),
+    // the nth value of input
+    Input(usize),
+}
+
+// What a run which halted left behind
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    // cell 0, which day 2's programs leave their answer in
+    pub exit: i64,
+    pub output: Vec<i64>,
+}
+
+// A set of values, one per slot in the order they were added, and the run
+// they gave
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub values: Vec<i64>,
+    pub run: Run,
+}
+
+// Tries a program on every combination of values for some of its memory
+// cells and inputs, looking for runs which a predicate accepts.  Runs which
+// fault, want more input than they have, or don't halt never match.
+pub struct Search {
+    base: IntcodeMachine<BufferIo>,
+    input: Vec<i64>,
+    slots: Vec<(Slot, RangeInclusive<i64>)>,
+    // how many combinations of values there are to try
+    combinations: usize,
+    threads: usize,
+}
+
+impl Search {
+    pub fn new(program: Vec<i64>) -> Search {
+        Search {
+            base: IntcodeMachine::with_io(program, BufferIo::default()),
+            input: Vec::new(),
+            slots: Vec::new(),
+            combinations: 1,
+            threads: 1,
+        }
+    }
+
+    // input given to every run, before any Input slots are filled in
+    pub fn input(&mut self, input: Vec<i64>) {
+        self.input = input;
+    }
+
+    // Tries every value in `range` for `slot`.  Later slots vary fastest.
+    // Fails if there would be more combinations than can be counted, or the
+    // input is too far in.
+    pub fn vary(&mut self, slot: Slot, range: RangeInclusive<i64>) -> Result<(), String> {
+        if let Slot::Input(n) = slot {
+            if n >= INPUT_LIMIT {
+                return Err(format!("input {} is past the last one a search can vary ({})", n, INPUT_LIMIT - 1));
+            }
+        }
+        self.combinations = span(&range)
+            .and_then(|len| self.combinations.checked_mul(len))
+            .ok_or_else(|| format!("too many combinations to search with {:?}", range))?;
+        self.slots.push((slot, range));
+        Ok(())
+    }
+
+    pub fn parallel(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    // The solution which comes first in the order slots are varied in.  With
+    // several threads, each stops as soon as a solution earlier than any it
+    // could still find is known.
+    pub fn first<P>(&self, accept: P) -> Option<Solution>
+    where
+        P: Fn(&Run) -> bool + Sync,
+    {
+        let best = AtomicUsize::new(usize::MAX);
+        let found = Mutex::new(None);
+        self.scan(|index| {
+            if index > best.load(Ordering::Relaxed) {
+                return false;
+            }
+            if let Some(solution) = self.attempt(index, &accept) {
+                let mut found = found.lock().unwrap();
+                if index < best.load(Ordering::Relaxed) {
+                    best.store(index, Ordering::Relaxed);
+                    *found = Some(solution);
+                }
+                return false;
+            }
+            true
+        });
+        found.into_inner().unwrap()
+    }
+
+    // every solution, in the order slots are varied in
+    pub fn all<P>(&self, accept: P) -> Vec<Solution>
+    where
+        P: Fn(&Run) -> bool + Sync,
+    {
+        let found = Mutex::new(Vec::new());
+        self.scan(|index| {
+            if let Some(solution) = self.attempt(index, &accept) {
+                found.lock().unwrap().push((index, solution));
+            }
+            true
+        });
+        let mut found = found.into_inner().unwrap();
+        found.sort_by_key(|(index, _)| *index);
+        found.into_iter().map(|(_, solution)| solution).collect()
+    }
+
+    // Calls `visit` on the index of each combination, thread t taking every
+    // nth one from t on, in order, until `visit` says to stop.
+    fn scan<V>(&self, visit: V)
+    where
+        V: Fn(usize) -> bool + Sync,
+    {
+        let total = self.combinations;
+        let visit = &visit;
+        thread::scope(|scope| {
+            for t in 0..self.threads {
+                scope.spawn(move || {
+                    for index in (t..total).step_by(self.threads) {
+                        if !visit(index) {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    // the value of each slot in the `index`th combination
+    fn values(&self, mut index: usize) -> Vec<i64> {
+        let mut values = vec![0; self.slots.len()];
+        for (i, (_, range)) in self.slots.iter().enumerate().rev() {
+            // vary made sure the lengths fit; the offset may not fit in an
+            // i64, but the value it leads to does
+            let len = span(range).unwrap();
+            values[i] = range.start().wrapping_add((index % len) as i64);
+            index /= len;
+        }
+        values
+    }
+
+    fn attempt<P>(&self, index: usize, accept: &P) -> Option<Solution>
+    where
+        P: Fn(&Run) -> bool,
+    {
+        let values = self.values(index);
+        let mut mach = self.base.clone();
+        let mut input = self.input.to_owned();
+        for ((slot, _), value) in self.slots.iter().zip(values.iter()) {
+            match *slot {
+                Slot::Cell(addr) => mach.poke(addr, *value),
+                Slot::Input(n) => {
+                    if input.len() <= n {
+                        input.resize(n + 1, 0);
+                    }
+                    input[n] = *value;
+                },
+            }
+        }
+        mach.io_mut().input.extend(input);
+
+        let mut budget = RUN_LIMIT;
+        loop {
+            match mach.run_within(&mut budget) {
+                Ok(StopReason::Output(_)) => continue,
+                Ok(StopReason::Halted) => break,
+                _ => return None,
+            }
+        }
+        let run = Run {
+            exit: mach.peek(0),
+            output: mach.io_mut().output.drain(..).collect(),
+        };
+        if accept(&run) {
+            Some(Solution { values, run })
+        } else {
+            None
+        }
+    }
+}
+
+// how many values `range` holds, if that fits in a usize
+fn span(range: &RangeInclusive<i64>) -> Option<usize> {
+    let len = (*range.end() as i128 - *range.start() as i128 + 1).max(0);
+    usize::try_from(len).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // leaves [1] * [2] + 3 in cell 0, and outputs its input doubled
+    const PROGRAM: [i64; 17] = [1102,0,0,0, 1001,0,3,0, 3,20, 102,2,20,20, 4,20, 99];
+
+    fn search(threads: usize) -> Search {
+        let mut search = Search::new(PROGRAM.to_vec());
+        search.vary(Slot::Cell(1), 0..=9).unwrap();
+        search.vary(Slot::Cell(2), 0..=9).unwrap();
+        search.parallel(threads);
+        search
+    }
+
+    #[test]
+    fn test_first() {
+        for threads in 1..4 {
+            let mut search = search(threads);
+            search.input(vec![21]);
+            let found = search.first(|run| run.exit == 12 + 3);
+            assert_eq!(Some(Solution {
+                values: vec![2, 6],
+                run: Run { exit: 15, output: vec![42] },
+            }), found, "{} threads", threads);
+            assert_eq!(None, search.first(|run| run.exit == 1000));
+        }
+    }
+
+    #[test]
+    fn test_all() {
+        for threads in 1..4 {
+            let mut search = search(threads);
+            search.input(vec![0]);
+            let found: Vec<Vec<i64>> = search.all(|run| run.exit == 12 + 3)
+                .into_iter()
+                .map(|s| s.values)
+                .collect();
+            assert_eq!(vec![vec![2,6], vec![3,4], vec![4,3], vec![6,2]], found, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_inputs() {
+        // outputs the sum of two inputs
+        let mut search = Search::new([3,11,3,12,1,11,12,13,4,13,99,0,0,0].to_vec());
+        search.input(vec![5]);
+        search.vary(Slot::Input(1), -10..=10).unwrap();
+        let found = search.first(|run| run.output == vec![-1]);
+        assert_eq!(Some(vec![-6]), found.map(|s| s.values));
+
+        // runs which want more input than they get never match
+        let mut search = Search::new([3,11,3,12,1,11,12,13,4,13,99,0,0,0].to_vec());
+        search.vary(Slot::Input(0), -10..=10).unwrap();
+        assert!(search.all(|_| true).is_empty());
+    }
+
+    #[test]
+    fn test_size() {
+        let mut search = Search::new(PROGRAM.to_vec());
+        assert!(search.vary(Slot::Cell(1), i64::MIN..=i64::MAX).is_err());
+        search.vary(Slot::Cell(1), i64::MIN..=-1).unwrap();
+        assert!(search.vary(Slot::Cell(2), 0..=1).is_err());
+        assert!(search.vary(Slot::Input(INPUT_LIMIT), 0..=0).is_err());
+        // an empty range leaves nothing to try
+        search.vary(Slot::Cell(2), RangeInclusive::new(1, 0)).unwrap();
+        assert!(search.all(|_| true).is_empty());
+
+        // values near the ends of the widest range a search can take
+        let mut search = Search::new(PROGRAM.to_vec());
+        search.vary(Slot::Cell(1), -5..=i64::MAX).unwrap();
+        assert_eq!(vec![-5, i64::MAX], vec![search.values(0)[0], search.values(i64::MAX as usize + 5)[0]]);
+    }
+
+    #[test]
+    fn test_run_limit() {
+        // outputs forever, so never halts however often it stops for output
+        let mut search = Search::new([104,1,1105,1,0].to_vec());
+        search.vary(Slot::Cell(1), 0..=0).unwrap();
+        assert!(search.all(|_| true).is_empty());
+    }
+}
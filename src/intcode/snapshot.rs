@@ -0,0 +1,237 @@
+use std::fmt;
+use std::io::BufRead;
+use super::{Arithmetic, IntcodeError, IntcodeIo, IntcodeMachine};
+use super::memory::Memory;
+use super::cache::DecodeCache;
+
+// The complete state of a machine at some point in its run.  A snapshot
+// prints as a small line-oriented text file, and can be read back from one:
+//
+//   ip 4
+//   rb 0
+//   in 7,8
+//   out
+//   mem 0 3,9,8,9,10,9,4,9,99,-1,8
+//   mem 1000000 42
+//
+// A machine without the default arithmetic has an `arith` line, and cells
+// holding values too wide for them have `wide <address> <value>` lines.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub ip: usize,
+    pub off: i64,
+    // input which was queued but not read, and output not yet collected
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+    pub arithmetic: Arithmetic,
+    memory: Memory,
+}
+
+impl<T: IntcodeIo> IntcodeMachine<T> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            off: self.off,
+            input: self.io.pending_input(),
+            output: self.io.pending_output(),
+            arithmetic: self.arithmetic,
+            memory: self.memory.clone(),
+        }
+    }
+}
+
+impl Snapshot {
+    // Builds an independent machine which carries on from this snapshot.
+    // Pending input and output are not replayed; hand them to `io` if wanted.
+    pub fn restore<T: IntcodeIo>(&self, io: T) -> IntcodeMachine<T> {
+        let mut mach = IntcodeMachine {
+            ip: self.ip,
+            off: self.off,
+            io,
+            memory: self.memory.clone(),
+            arithmetic: self.arithmetic,
+            cache: DecodeCache::default(),
+            baseline: false,
+            compiled: None,
+            trace: None,
+        };
+        if cfg!(feature = "compiled") {
+            mach.compile();
+        }
+        mach
+    }
+
+    pub fn read<I>(buf: I) -> Result<Snapshot, IntcodeError>
+    where
+        I: BufRead,
+    {
+        let mut snap = Snapshot {
+            ip: 0,
+            off: 0,
+            input: Vec::new(),
+            output: Vec::new(),
+            arithmetic: Arithmetic::default(),
+            memory: Memory::default(),
+        };
+        for (index, line) in buf.lines().enumerate() {
+            let line = line.map_err(|e| IntcodeError::Io(e.to_string()))?;
+            let bad_line = || IntcodeError::Parse {
+                line: index + 1,
+                column: 1,
+                token: line.to_string(),
+            };
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("ip") => {
+                    snap.ip = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                },
+                Some("rb") => {
+                    snap.off = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                },
+                Some("in") => {
+                    snap.input = parse_list(fields.next()).ok_or_else(bad_line)?;
+                },
+                Some("out") => {
+                    snap.output = parse_list(fields.next()).ok_or_else(bad_line)?;
+                },
+                Some("mem") => {
+                    let base: usize = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                    let cells = parse_list(fields.next()).ok_or_else(bad_line)?;
+                    if base == 0 {
+                        snap.memory = Memory::new(cells);
+                    } else {
+                        for (i, v) in cells.into_iter().enumerate() {
+                            snap.memory.set(base.checked_add(i).ok_or_else(bad_line)?, v);
+                        }
+                    }
+                },
+                Some("arith") => {
+                    snap.arithmetic = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                },
+                Some("wide") => {
+                    let addr = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                    let value = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad_line)?;
+                    snap.memory.set_wide(addr, value);
+                },
+                None => {},
+                Some(_) => return Err(bad_line()),
+            }
+        }
+        Ok(snap)
+    }
+}
+
+// parses a possibly missing, comma separated list of numbers
+fn parse_list(field: Option<&str>) -> Option<Vec<i64>> {
+    match field {
+        None => Some(Vec::new()),
+        Some(f) => f.split(',').map(|x| x.parse().ok()).collect(),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, values: &[i64]) -> fmt::Result {
+    for (i, v) in values.iter().enumerate() {
+        if i == 0 {
+            write!(f, " {}", v)?;
+        } else {
+            write!(f, ",{}", v)?;
+        }
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ip {}", self.ip)?;
+        writeln!(f, "rb {}", self.off)?;
+        if self.arithmetic != Arithmetic::default() {
+            writeln!(f, "arith {}", self.arithmetic)?;
+        }
+        write!(f, "in")?;
+        write_list(f, &self.input)?;
+        write!(f, "out")?;
+        write_list(f, &self.output)?;
+        write!(f, "mem 0")?;
+        write_list(f, self.memory.dense())?;
+        for (addr, v) in self.memory.sparse() {
+            writeln!(f, "mem {} {}", addr, v)?;
+        }
+        for (addr, v) in self.memory.wide() {
+            writeln!(f, "wide {} {}", addr, v)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Arithmetic, BufferIo, StopReason};
+
+    // reads an input, then counts down from it, outputting each step
+    const COUNTDOWN: [i64; 12] = [3,100,4,100,1001,100,-1,100,1005,100,2,99];
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut mach = IntcodeMachine::with_io(COUNTDOWN.to_vec(), BufferIo::new(vec![3]));
+        assert_eq!(Ok(StopReason::Output(3)), mach.run_until(None));
+
+        let mut fork = mach.clone();
+        fork.poke(100, 10);
+        fork.run_program().unwrap();
+        mach.run_program().unwrap();
+        assert_eq!(vec![3,2,1], Vec::from(mach.io_mut().output.to_owned()));
+        assert_eq!(vec![3,9,8,7,6,5,4,3,2,1],
+                   Vec::from(fork.io_mut().output.to_owned()));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut mach = IntcodeMachine::with_io(COUNTDOWN.to_vec(), BufferIo::new(vec![3, 42]));
+        mach.run_until(Some(3)).unwrap();
+        mach.poke(5_000_000, -7);
+        let snap = mach.snapshot();
+        assert_eq!(vec![42], snap.input);
+        assert_eq!(vec![3], snap.output);
+
+        let text = snap.to_string();
+        assert!(text.starts_with("ip 4\nrb 0\nin 42\nout 3\nmem 0 3,100,4,100,"));
+        assert!(text.ends_with("mem 5000000 -7\n"));
+
+        let read = Snapshot::read(text.as_bytes()).unwrap();
+        assert_eq!(text, read.to_string());
+        let mut resumed = read.restore(BufferIo::default());
+        resumed.run_program().unwrap();
+        mach.run_program().unwrap();
+        assert_eq!(vec![2,1], Vec::from(resumed.io_mut().output.to_owned()));
+        assert_eq!(vec![3,2,1], Vec::from(mach.io_mut().output.to_owned()));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_wide_values() {
+        // squares 2^40 into cell 9, then multiplies that by 2^24
+        let program: Vec<i64> = [1102,1<<40,1<<40,9,1002,9,1<<24,9,99,0].to_vec();
+        let mut mach = IntcodeMachine::with_arithmetic(program, BufferIo::default(), Arithmetic::Widened);
+        mach.step().unwrap();
+        let text = mach.snapshot().to_string();
+        assert!(text.contains("arith widened\n"));
+        assert!(text.ends_with(&format!("wide 9 {}\n", 1i128 << 80)));
+
+        let mut resumed = Snapshot::read(text.as_bytes()).unwrap().restore(BufferIo::default());
+        resumed.run_program().unwrap();
+        mach.run_program().unwrap();
+        assert_eq!(mach.snapshot().to_string(), resumed.snapshot().to_string());
+        assert!(resumed.snapshot().to_string().ends_with(&format!("wide 9 {}\n", 1i128 << 104)));
+    }
+
+    #[test]
+    fn test_read_bad_snapshot() {
+        assert_eq!(IntcodeError::Parse { line: 2, column: 1, token: "rb x".to_string() },
+                   Snapshot::read(&b"ip 0\nrb x\n"[..]).unwrap_err());
+        assert!(Snapshot::read(&b"ip 0\nmem 0 1,,2\n"[..]).is_err());
+        // the second cell would be past the last address
+        assert_eq!(IntcodeError::Parse { line: 2, column: 1, token: "mem 18446744073709551615 1,2".to_string() },
+                   Snapshot::read(&b"ip 0\nmem 18446744073709551615 1,2\n"[..]).unwrap_err());
+    }
+}
@@ -0,0 +1,457 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+use super::{Fault, Mode};
+use super::cache::decode_word;
+
+// most instructions evaluated before giving up, as the program may loop
+const SYMBOLIC_LIMIT: usize = 1_000_000;
+// most nodes an expression may have, counting shared parts each time they're
+// used, as simplifying and printing one walks all of them
+const EXPR_LIMIT: usize = 4096;
+
+// The value of a cell, in terms of the starting values of the cells chosen
+// as variables.  Sums and products share their operands, and know how many
+// nodes they have.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Const(i64),
+    // the starting value of the cell
+    Var(usize),
+    // something which can't be written in terms of the variables, such as a
+    // cell read through an address which depends on one
+    Unknown,
+    Add(Rc<Expr>, Rc<Expr>, usize),
+    Mul(Rc<Expr>, Rc<Expr>, usize),
+}
+
+impl Expr {
+    // Constants are folded as the tree is built, so that only the parts
+    // which depend on variables grow.  None if folding overflows, as the
+    // machine's checked arithmetic would.
+    fn add(a: Expr, b: Expr) -> Option<Expr> {
+        Some(match (a, b) {
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.checked_add(b)?),
+            (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+            (a, b) => {
+                let size = a.size().saturating_add(b.size()).saturating_add(1);
+                Expr::Add(Rc::new(a), Rc::new(b), size)
+            },
+        })
+    }
+
+    fn mul(a: Expr, b: Expr) -> Option<Expr> {
+        Some(match (a, b) {
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const(a.checked_mul(b)?),
+            (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+            (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+            (a, b) => {
+                let size = a.size().saturating_add(b.size()).saturating_add(1);
+                Expr::Mul(Rc::new(a), Rc::new(b), size)
+            },
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Expr::Add(_, _, size) | Expr::Mul(_, _, size) => *size,
+            _ => 1,
+        }
+    }
+
+    fn constant(&self) -> Option<i64> {
+        match self {
+            Expr::Const(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    // Multiplies the tree out into a sum of products of variables.  Fails if
+    // it depends on something Unknown, or a coefficient overflows.
+    pub fn simplify(&self) -> Result<Poly, SymbolicError> {
+        let mut poly = Poly::default();
+        match self {
+            Expr::Const(c) => poly.add_term(Vec::new(), *c as i128)?,
+            Expr::Var(v) => poly.add_term(vec![*v], 1)?,
+            Expr::Unknown => return Err(SymbolicError::Unknown),
+            Expr::Add(a, b, _) => {
+                let (a, b) = (a.simplify()?, b.simplify()?);
+                for (vars, c) in a.terms.into_iter().chain(b.terms) {
+                    poly.add_term(vars, c)?;
+                }
+            },
+            Expr::Mul(a, b, _) => {
+                let (a, b) = (a.simplify()?, b.simplify()?);
+                for (va, ca) in a.terms.iter() {
+                    for (vb, cb) in b.terms.iter() {
+                        let mut vars: Vec<usize> = va.iter().chain(vb.iter()).cloned().collect();
+                        vars.sort_unstable();
+                        poly.add_term(vars, ca.checked_mul(*cb).ok_or(SymbolicError::Overflow)?)?;
+                    }
+                }
+            },
+        }
+        Ok(poly)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(c) => write!(f, "{}", c),
+            Expr::Var(v) => write!(f, "[{}]", v),
+            Expr::Unknown => write!(f, "?"),
+            Expr::Add(a, b, _) => write!(f, "({} + {})", a, b),
+            Expr::Mul(a, b, _) => write!(f, "{} * {}", a, b),
+        }
+    }
+}
+
+// A sum of terms, each a coefficient times a product of variables
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Poly {
+    // the variables multiplied together, in order, and their coefficient;
+    // the constant term has no variables
+    terms: BTreeMap<Vec<usize>, i128>,
+}
+
+impl Poly {
+    fn add_term(&mut self, vars: Vec<usize>, c: i128) -> Result<(), SymbolicError> {
+        let sum = self.coefficient(&vars).checked_add(c).ok_or(SymbolicError::Overflow)?;
+        if sum == 0 {
+            self.terms.remove(&vars);
+        } else {
+            self.terms.insert(vars, sum);
+        }
+        Ok(())
+    }
+
+    fn coefficient(&self, vars: &[usize]) -> i128 {
+        *self.terms.get(vars).unwrap_or(&0)
+    }
+
+    // Every assignment of values from `ranges` which makes the polynomial
+    // equal `target`, in order with later variables varying fastest.  Only
+    // linear polynomials can be solved: all but one variable are tried in
+    // turn, and the last is solved for.
+    pub fn solve(&self, target: i64, ranges: &[(usize, RangeInclusive<i64>)]) -> Result<Vec<Vec<i64>>, SymbolicError> {
+        if self.terms.keys().any(|vars| vars.len() > 1) {
+            return Err(SymbolicError::NotLinear(self.to_string()));
+        }
+        let unsolved = self.terms.keys().flatten().find(|v| !ranges.iter().any(|(r, _)| r == *v));
+        if let Some(v) = unsolved {
+            return Err(SymbolicError::NoRange(*v));
+        }
+        let coeffs: Vec<i128> = ranges.iter().map(|(v, _)| self.coefficient(&[*v])).collect();
+        let last = coeffs.iter().rposition(|c| *c != 0);
+
+        let mut solutions = Vec::new();
+        let mut values: Vec<i64> = ranges.iter().map(|(_, r)| *r.start()).collect();
+        if ranges.iter().any(|(_, r)| r.is_empty()) {
+            return Ok(solutions);
+        }
+        loop {
+            let mut rest = (target as i128).checked_sub(self.coefficient(&[])).ok_or(SymbolicError::Overflow)?;
+            for (i, c) in coeffs.iter().enumerate() {
+                if Some(i) != last {
+                    let term = c.checked_mul(values[i] as i128).ok_or(SymbolicError::Overflow)?;
+                    rest = rest.checked_sub(term).ok_or(SymbolicError::Overflow)?;
+                }
+            }
+            match last {
+                Some(i) if rest.checked_rem(coeffs[i]).ok_or(SymbolicError::Overflow)? == 0 => {
+                    let x = rest / coeffs[i];
+                    if x == x as i64 as i128 && ranges[i].1.contains(&(x as i64)) {
+                        values[i] = x as i64;
+                        solutions.push(values.to_owned());
+                    }
+                },
+                Some(_) => {},
+                None if rest == 0 => solutions.push(values.to_owned()),
+                None => {},
+            }
+            // step the free variables on, like an odometer
+            let mut carry = true;
+            for i in (0..ranges.len()).rev().filter(|i| Some(*i) != last) {
+                if values[i] < *ranges[i].1.end() {
+                    values[i] += 1;
+                    carry = false;
+                    break;
+                }
+                values[i] = *ranges[i].1.start();
+            }
+            if carry {
+                break;
+            }
+        }
+        solutions.sort();
+        Ok(solutions)
+    }
+}
+
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        // highest degree first, so that the constant comes last
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        terms.sort_by_key(|(vars, _)| (usize::MAX - vars.len(), vars.to_owned()));
+        for (i, (vars, c)) in terms.into_iter().enumerate() {
+            let sign = if *c < 0 { "-" } else { "+" };
+            match i {
+                0 if *c < 0 => write!(f, "-")?,
+                0 => {},
+                _ => write!(f, " {} ", sign)?,
+            }
+            let names: Vec<String> = vars.iter().map(|v| format!("[{}]", v)).collect();
+            match (c.abs(), names.is_empty()) {
+                (c, true) => write!(f, "{}", c)?,
+                (1, false) => write!(f, "{}", names.join("*"))?,
+                (c, false) => write!(f, "{}*{}", c, names.join("*"))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolicError {
+    // the machine faulted at the given ip
+    Fault(usize, Fault),
+    // an instruction, the address it writes to, or whether it jumps,
+    // depends on the variables
+    Opcode(usize),
+    Write(usize),
+    Jump(usize),
+    // the program wanted input, which isn't modelled
+    Input(usize),
+    // the program was still running after the limit
+    StepLimit,
+    // an expression grew too large to follow, at the given ip
+    TooLarge(usize),
+    // the result depends on something which isn't a function of the variables
+    Unknown,
+    // a coefficient went out of range while simplifying or solving
+    Overflow,
+    NotLinear(String),
+    // a variable in the result was given no range to solve over
+    NoRange(usize),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolicError::Fault(ip, fault) => write!(f, "{} at ip {}", fault, ip),
+            SymbolicError::Opcode(ip) => write!(f, "instruction at ip {} depends on the variables", ip),
+            SymbolicError::Write(ip) => write!(f, "write address at ip {} depends on the variables", ip),
+            SymbolicError::Jump(ip) => write!(f, "jump at ip {} depends on the variables", ip),
+            SymbolicError::Input(ip) => write!(f, "input wanted at ip {}", ip),
+            SymbolicError::StepLimit => write!(f, "still running after {} instructions", SYMBOLIC_LIMIT),
+            SymbolicError::TooLarge(ip) => write!(f, "expression at ip {} has more than {} nodes", ip, EXPR_LIMIT),
+            SymbolicError::Unknown => write!(f, "result is not a function of the variables"),
+            SymbolicError::Overflow => write!(f, "coefficient overflows"),
+            SymbolicError::NotLinear(poly) => write!(f, "can't solve {} = target, which isn't linear", poly),
+            SymbolicError::NoRange(v) => write!(f, "no range for [{}]", v),
+        }
+    }
+}
+
+// Runs `program` with the cells in `vars` treated as unknowns, and returns
+// the final contents of memory, leaving out cells never given a value.  Only
+// straight-line code can be followed: control flow and the addresses written
+// to must not depend on the unknowns.
+pub fn evaluate(program: &[i64], vars: &[usize]) -> Result<BTreeMap<usize, Expr>, SymbolicError> {
+    let mut memory: BTreeMap<usize, Expr> = program.iter().map(|w| Expr::Const(*w)).enumerate().collect();
+    for v in vars {
+        memory.insert(*v, Expr::Var(*v));
+    }
+    let mut ip = 0;
+    let mut off = 0;
+    for _ in 0..SYMBOLIC_LIMIT {
+        let fault = |fault| SymbolicError::Fault(ip, fault);
+        let word = cell(&memory, ip).constant().ok_or(SymbolicError::Opcode(ip))?;
+        let (opcode, modes) = decode_word(word).map_err(fault)?;
+
+        // the value of parameter n, and the address parameter n points to
+        let param = |n: usize| -> Result<Expr, SymbolicError> {
+            let arg = cell(&memory, ip + n + 1);
+            let addr = match (modes[n], arg.constant()) {
+                (Mode::Value, _) => return Ok(arg),
+                (_, None) => return Ok(Expr::Unknown),
+                (Mode::Pointer, Some(a)) => a,
+                (Mode::Relative, Some(a)) => a.checked_add(off).ok_or_else(|| fault(Fault::Overflow))?,
+            };
+            if addr < 0 {
+                return Err(fault(Fault::NegativeAddress(addr)));
+            }
+            Ok(cell(&memory, addr as usize))
+        };
+        let target = |n: usize| -> Result<usize, SymbolicError> {
+            let arg = cell(&memory, ip + n + 1).constant().ok_or(SymbolicError::Write(ip))?;
+            let addr = match modes[n] {
+                Mode::Value => return Err(fault(Fault::ValueModeWrite)),
+                Mode::Pointer => arg,
+                Mode::Relative => arg.checked_add(off).ok_or_else(|| fault(Fault::Overflow))?,
+            };
+            if addr < 0 {
+                return Err(fault(Fault::NegativeAddress(addr)));
+            }
+            Ok(addr as usize)
+        };
+        let jump = |n: usize| -> Result<i64, SymbolicError> {
+            param(n)?.constant().ok_or(SymbolicError::Jump(ip))
+        };
+
+        let overflow = || fault(Fault::Overflow);
+        let (write, next) = match opcode {
+            1 => {
+                let sum = Expr::add(param(0)?, param(1)?).ok_or_else(overflow)?;
+                (Some((target(2)?, sum)), ip + 4)
+            },
+            2 => {
+                let product = Expr::mul(param(0)?, param(1)?).ok_or_else(overflow)?;
+                (Some((target(2)?, product)), ip + 4)
+            },
+            3 => return Err(SymbolicError::Input(ip)),
+            // output isn't kept; only what's left in memory matters
+            4 => (None, ip + 2),
+            5 | 6 => {
+                let taken = (jump(0)? != 0) == (opcode == 5);
+                let to = jump(1)?;
+                if to < 0 && taken {
+                    return Err(fault(Fault::NegativeAddress(to)));
+                }
+                (None, if taken { to as usize } else { ip + 3 })
+            },
+            7 | 8 => {
+                let value = match (param(0)?.constant(), param(1)?.constant()) {
+                    (Some(a), Some(b)) if opcode == 7 => Expr::Const((a < b) as i64),
+                    (Some(a), Some(b)) => Expr::Const((a == b) as i64),
+                    _ => Expr::Unknown,
+                };
+                (Some((target(2)?, value)), ip + 4)
+            },
+            9 => {
+                off = off.checked_add(jump(0)?).ok_or_else(overflow)?;
+                (None, ip + 2)
+            },
+            99 => return Ok(memory),
+            _ => return Err(fault(Fault::BadOpcode)),
+        };
+        if let Some((addr, value)) = write {
+            if value.size() > EXPR_LIMIT {
+                return Err(SymbolicError::TooLarge(ip));
+            }
+            memory.insert(addr, value);
+        }
+        ip = next;
+    }
+    Err(SymbolicError::StepLimit)
+}
+
+fn cell(memory: &BTreeMap<usize, Expr>, addr: usize) -> Expr {
+    memory.get(&addr).cloned().unwrap_or(Expr::Const(0))
+}
+
+// What a day 2 style program leaves in cell 0, as a polynomial in `vars`.
+pub fn exit_value(program: &[i64], vars: &[usize]) -> Result<Poly, SymbolicError> {
+    let memory = evaluate(program, vars)?;
+    cell(&memory, 0).simplify()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::read_program;
+
+    #[test]
+    fn test_evaluate() {
+        // [0] = ([13] + [14]) * 3 + [13]
+        let program: Vec<i64> = [1,13,14,0, 1002,0,3,0, 1,0,13,0, 99, 0, 0].to_vec();
+        let memory = evaluate(&program, &[13, 14]).unwrap();
+        assert_eq!("(([13] + [14]) * 3 + [13])", memory[&0].to_string());
+        assert_eq!("4*[13] + 3*[14]", memory[&0].simplify().unwrap().to_string());
+
+        // an instruction whose first operand address is a variable reads
+        // something unknown, but it doesn't matter once overwritten
+        let program: Vec<i64> = [1,0,0,3, 1,1,2,3, 1101,0,7,0, 99].to_vec();
+        let memory = evaluate(&program, &[1, 2]).unwrap();
+        assert_eq!(Expr::Const(7), memory[&0]);
+        assert_eq!("[1] + [2]", memory[&3].simplify().unwrap().to_string());
+        assert_eq!(Ok("7".to_string()), exit_value(&program, &[1, 2]).map(|p| p.to_string()));
+    }
+
+    #[test]
+    fn test_bail_out() {
+        // jumps if [1] is true
+        let program: Vec<i64> = [1005,1,4,99,99].to_vec();
+        assert_eq!(Err(SymbolicError::Jump(0)), evaluate(&program, &[1]));
+        // but not if it jumps on a constant
+        assert!(evaluate(&program, &[3]).is_ok());
+        // writes to [[3]]
+        let program: Vec<i64> = [1101,1,1,0,99].to_vec();
+        assert_eq!(Err(SymbolicError::Write(0)), evaluate(&program, &[3]));
+        // [0] = [[1]]
+        let program: Vec<i64> = [1,1,5,0,99,0].to_vec();
+        assert_eq!(Err(SymbolicError::Unknown), exit_value(&program, &[1]));
+        // runs for ever
+        assert_eq!(Err(SymbolicError::StepLimit), evaluate(&[1105,1,0], &[]));
+        // adds [20] to [19] twenty thousand times
+        let program: Vec<i64> = [1,19,20,19, 1001,18,-1,18, 1005,18,0, 99, 0,0,0,0,0,0, 20000, 0, 0].to_vec();
+        assert_eq!(Err(SymbolicError::TooLarge(0)), evaluate(&program, &[20]));
+        // [0] = ([1] + [2]) * i64::MAX cubed, which no i128 holds
+        let program: Vec<i64> = [1,0,0,3, 1,1,2,0, 1002,0,i64::MAX,0, 1002,0,i64::MAX,0, 1002,0,i64::MAX,0, 99].to_vec();
+        assert_eq!(Err(SymbolicError::Overflow), exit_value(&program, &[1, 2]));
+    }
+
+    #[test]
+    fn test_faults() {
+        let overflow = |ip| Err(SymbolicError::Fault(ip, Fault::Overflow));
+        // folding constants which overflow, as the machine checks they don't
+        assert_eq!(overflow(0), evaluate(&[1101,i64::MAX,1,0,99], &[]));
+        assert_eq!(overflow(0), evaluate(&[1102,i64::MAX,2,0,99], &[]));
+        // the relative base, and an address relative to it, going past i64::MAX
+        assert_eq!(overflow(2), evaluate(&[109,i64::MAX,109,1,99], &[]));
+        assert_eq!(overflow(2), evaluate(&[109,1,21101,1,1,i64::MAX,99], &[]));
+
+        // a far write needs no more memory than the cell it writes
+        let memory = evaluate(&[1101,1,1,1_000_000_000_000,99], &[]).unwrap();
+        assert_eq!(Some(&Expr::Const(2)), memory.get(&1_000_000_000_000));
+    }
+
+    #[test]
+    fn test_solve() {
+        // 3x - 2y + 1
+        let mut poly = Poly::default();
+        poly.add_term(vec![], 1).unwrap();
+        poly.add_term(vec![1], 3).unwrap();
+        poly.add_term(vec![2], -2).unwrap();
+        assert_eq!("3*[1] - 2*[2] + 1", poly.to_string());
+        let ranges = [(1, 0..=9), (2, 0..=9)];
+        assert_eq!(Ok(vec![vec![1, 0], vec![3, 3], vec![5, 6], vec![7, 9]]), poly.solve(4, &ranges));
+        assert_eq!(Ok(vec![]), poly.solve(1000, &ranges));
+        assert_eq!(Err(SymbolicError::NoRange(2)), poly.solve(4, &ranges[..1]));
+
+        poly.add_term(vec![1, 2], 1).unwrap();
+        assert!(poly.solve(4, &ranges).is_err());
+
+        // coefficients too big to solve with
+        let mut poly = Poly::default();
+        poly.add_term(vec![], i128::MIN).unwrap();
+        assert_eq!(Err(SymbolicError::Overflow), poly.add_term(vec![], -1));
+        assert_eq!(Err(SymbolicError::Overflow), poly.solve(4, &ranges));
+    }
+
+    #[test]
+    fn test_day_two() {
+        let program = read_program(&include_bytes!("../../2/input")[..]).unwrap();
+        let poly = exit_value(&program, &[1, 2]).unwrap();
+        assert_eq!(Ok(vec![vec![12, 2]]), poly.solve(3562624, &[(1, 12..=12), (2, 0..=99)]));
+        assert_eq!(Ok(vec![vec![82, 98]]), poly.solve(19690720, &[(1, 0..=99), (2, 0..=99)]));
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use super::{IntcodeIo, IntcodeMachine, Mode};
+use super::cache::decode_word;
+use super::disasm::{Instruction, OPS};
+
+// One executed instruction, as seen by a trace
+#[derive(Clone)]
+pub struct TraceEvent {
+    pub ip: usize,
+    pub instruction: Instruction,
+    // what each operand that is read from read as, or None if its address
+    // couldn't be worked out
+    pub values: Vec<Option<i64>>,
+    // the cells those operands were read from, leaving out immediate values
+    pub reads: Vec<usize>,
+    // the cell written to and the value it was given
+    pub write: Option<(usize, i128)>,
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = format!("{:04}: {}", self.ip, self.instruction);
+        let mut notes: Vec<String> = self.values.iter()
+            .map(|v| v.map_or("?".to_string(), |v| v.to_string()))
+            .collect();
+        if let Some((addr, value)) = self.write {
+            notes.push(format!("-> [{}] = {}", addr, value));
+        }
+        if notes.is_empty() {
+            write!(f, "{}", line)
+        } else {
+            write!(f, "{:<36} ; {}", line, notes.join(" "))
+        }
+    }
+}
+
+// Somewhere to send a machine's trace
+pub trait TraceSink {
+    fn record(&mut self, event: &TraceEvent);
+}
+
+pub type SharedSink = Arc<Mutex<dyn TraceSink + Send>>;
+
+impl TraceSink for Vec<TraceEvent> {
+    fn record(&mut self, event: &TraceEvent) {
+        self.push(event.clone());
+    }
+}
+
+// Writes the trace as text, one instruction per line.  If a write fails,
+// nothing more is written, and the error is kept for the caller to report.
+pub struct TraceLog<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceLog<W> {
+    pub fn new(writer: W) -> TraceLog<W> {
+        TraceLog { writer, error: None }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl<W: Write> TraceSink for TraceLog<W> {
+    fn record(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", event).err();
+        }
+    }
+}
+
+// Counts instructions executed at each address and of each opcode
+#[derive(Default)]
+pub struct Profiler {
+    total: u64,
+    // address -> (times executed, instruction as first seen there)
+    hits: HashMap<usize, (u64, String)>,
+    ops: BTreeMap<&'static str, u64>,
+}
+
+impl TraceSink for Profiler {
+    fn record(&mut self, event: &TraceEvent) {
+        self.total += 1;
+        self.hits.entry(event.ip)
+                 .or_insert_with(|| (0, event.instruction.to_string()))
+                 .0 += 1;
+        *self.ops.entry(event.instruction.op.name).or_insert(0) += 1;
+    }
+}
+
+impl Profiler {
+    // Writes totals per opcode, and the `top` most executed addresses.
+    pub fn report<W: Write>(&self, mut out: W, top: usize) {
+        let pct = |n: u64| 100.0 * n as f64 / self.total.max(1) as f64;
+        writeln!(out, "{} instructions executed", self.total).unwrap();
+
+        writeln!(out, "\nby opcode:").unwrap();
+        let mut ops: Vec<_> = self.ops.iter().collect();
+        ops.sort_by(|a, b| b.1.cmp(a.1));
+        for (name, n) in ops {
+            writeln!(out, "  {:<4} {:>12} {:>6.2}%", name, n, pct(*n)).unwrap();
+        }
+
+        writeln!(out, "\nhot spots:").unwrap();
+        let mut hits: Vec<_> = self.hits.iter().collect();
+        hits.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then(a.0.cmp(b.0)));
+        for (addr, (n, text)) in hits.into_iter().take(top) {
+            writeln!(out, "  {:04} {:>12} {:>6.2}%  {}", addr, n, pct(*n), text).unwrap();
+        }
+    }
+}
+
+impl<T: IntcodeIo> IntcodeMachine<T> {
+    // Sends every instruction executed from now on to `sink`.
+    pub fn trace(&mut self, sink: SharedSink) {
+        self.trace = Some(sink);
+    }
+
+    // Decodes the instruction about to execute, for the trace, the same way
+    // the machine itself will.
+    pub(super) fn begin_trace(&self) -> Option<TraceEvent> {
+        let (opcode, modes) = decode_word(self.memory.get(self.ip)).ok()?;
+        let op = OPS.iter().find(|op| op.code == opcode as i64)?;
+        let args = (0..op.params).map(|n| (modes[n], self.memory.get(self.ip + n + 1))).collect();
+        let instruction = Instruction { op, args };
+        let read_args = instruction.args.len() - instruction.op.writes as usize;
+        let mut values = Vec::new();
+        let mut addrs = Vec::new();
+        for (mode, v) in instruction.args[..read_args].iter() {
+            let addr = match mode {
+                Mode::Value => {
+                    values.push(Some(*v));
+                    continue;
+                },
+                Mode::Pointer => Some(*v),
+                Mode::Relative => v.checked_add(self.off),
+            };
+            match addr {
+                Some(addr) if addr >= 0 => {
+                    values.push(Some(self.memory.get(addr as usize)));
+                    addrs.push(addr as usize);
+                },
+                _ => values.push(None),
+            }
+        }
+        Some(TraceEvent {
+            ip: self.ip,
+            instruction,
+            values,
+            reads: addrs,
+            write: None,
+        })
+    }
+
+    // Fills in what the instruction wrote and hands the event to the sink.
+    pub(super) fn end_trace(&self, mut event: TraceEvent) {
+        if event.instruction.op.writes {
+            let (mode, v) = event.instruction.args[event.instruction.args.len() - 1];
+            let addr = match mode {
+                Mode::Relative => v.checked_add(self.off),
+                _ => Some(v),
+            };
+            event.write = addr.map(|addr| (addr as usize, self.memory.get_wide(addr as usize)));
+        }
+        if let Some(sink) = self.trace.as_ref() {
+            sink.lock().unwrap().record(&event);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::BufferIo;
+
+    #[test]
+    fn test_trace() {
+        let eq_8_ptr: Vec<i64> = [3,9,8,9,10,9,4,9,99,-1,8].to_vec();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut mach = IntcodeMachine::with_io(eq_8_ptr, BufferIo::new(vec![8]));
+        mach.trace(events.clone());
+        mach.run_program().unwrap();
+
+        let lines: Vec<String> = events.lock().unwrap().iter().map(|e| e.to_string()).collect();
+        assert_eq!(vec![
+            "0000: IN [9]                         ; -> [9] = 8",
+            "0002: EQ [9], [10], [9]              ; 8 8 -> [9] = 1",
+            "0006: OUT [9]                        ; 1",
+            "0008: HLT",
+        ], lines);
+    }
+
+    #[test]
+    fn test_trace_decodes_as_the_machine_does() {
+        // an OUT with mode digits it has no parameters for
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut mach = IntcodeMachine::with_io([11104,7,99].to_vec(), BufferIo::default());
+        mach.trace(events.clone());
+        mach.run_program().unwrap();
+        let lines: Vec<String> = events.lock().unwrap().iter().map(|e| e.to_string()).collect();
+        assert_eq!(vec!["0000: OUT #7                         ; 7", "0002: HLT"], lines);
+
+        // a relative base too big to add an operand to
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut mach = IntcodeMachine::with_io([109,i64::MAX,204,1,99].to_vec(), BufferIo::default());
+        mach.trace(events.clone());
+        assert!(mach.run_program().is_err());
+        assert_eq!(1, events.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_trace_log_write_error() {
+        // room for the first line of the trace, but not the second
+        let log = Arc::new(Mutex::new(TraceLog::new(io::Cursor::new([0u8; 48]))));
+        let mut mach = IntcodeMachine::with_io([3,9,8,9,10,9,4,9,99,-1,8].to_vec(), BufferIo::new(vec![8]));
+        mach.trace(log.clone());
+        mach.run_program().unwrap();
+        assert_eq!(Some(&1), mach.io().output.back());
+
+        let log = log.lock().unwrap();
+        assert_eq!(io::ErrorKind::WriteZero, log.error().unwrap().kind());
+        let written = String::from_utf8_lossy(log.writer.get_ref()).to_string();
+        assert!(written.starts_with("0000: IN [9]"));
+        assert!(!written.contains("0002"));
+    }
+
+    #[test]
+    fn test_trace_skips_waiting_for_input() {
+        let mut mach = IntcodeMachine::with_io([3,0,99].to_vec(), BufferIo::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        mach.trace(events.clone());
+        mach.step().unwrap();
+        mach.step().unwrap();
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_profiler() {
+        // counts down from 3
+        let countdown: Vec<i64> = [3,100,4,100,1001,100,-1,100,1005,100,2,99].to_vec();
+        let profiler = Arc::new(Mutex::new(Profiler::default()));
+        let mut mach = IntcodeMachine::with_io(countdown, BufferIo::new(vec![3]));
+        mach.trace(profiler.clone());
+        mach.run_program().unwrap();
+
+        let mut report = Vec::new();
+        profiler.lock().unwrap().report(&mut report, 2);
+        assert_eq!("\
+11 instructions executed
+
+by opcode:
+  ADD             3  27.27%
+  JT              3  27.27%
+  OUT             3  27.27%
+  HLT             1   9.09%
+  IN              1   9.09%
+
+hot spots:
+  0002            3  27.27%  OUT [100]
+  0004            3  27.27%  ADD [100], #-1, [100]
+", String::from_utf8(report).unwrap());
+    }
+}
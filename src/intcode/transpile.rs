@@ -0,0 +1,374 @@
+use std::fmt::Write;
+use super::Mode;
+use super::cache::decode_word;
+
+// Everything a transpiled program needs besides its memory and dispatch
+// loop.  Faults are reported as IntcodeError displays them.
+const PRELUDE: &str = r#"use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead};
+use std::process::exit;
+
+// how far past the end of mem a write may land and still grow it
+const SLACK: usize = 0x1000;
+
+struct Machine {
+    mem: Vec<i64>,
+    // cells written far past the end of mem
+    far: BTreeMap<usize, i64>,
+    ip: usize,
+    rb: i64,
+    input: VecDeque<i64>,
+}
+
+impl Machine {
+    fn fault(&self, fault: &str) -> ! {
+        eprintln!("{} at ip {} (instruction {}, relative base {})",
+                  fault, self.ip, self.get(self.ip), self.rb);
+        exit(1)
+    }
+
+    fn halt(&self) -> ! {
+        eprintln!("halted with {} in cell 0", self.get(0));
+        exit(0)
+    }
+
+    fn addr(&self, a: i64) -> usize {
+        if a < 0 {
+            self.fault(&format!("negative address {}", a));
+        }
+        a as usize
+    }
+
+    fn get(&self, a: usize) -> i64 {
+        match self.mem.get(a) {
+            Some(v) => *v,
+            None => self.far.get(&a).copied().unwrap_or(0),
+        }
+    }
+
+    fn set(&mut self, a: usize, v: i64) {
+        if a >= self.mem.len() {
+            if a - self.mem.len() >= SLACK {
+                self.far.insert(a, v);
+                return;
+            }
+            let start = self.mem.len();
+            self.mem.resize(a + 1, 0);
+            let moved: Vec<usize> = self.far.range(start..=a).map(|(a, _)| *a).collect();
+            for b in moved {
+                self.mem[b] = self.far.remove(&b).unwrap();
+            }
+        }
+        self.mem[a] = v;
+    }
+
+    fn add(&self, a: i64, b: i64) -> i64 {
+        a.checked_add(b).unwrap_or_else(|| self.fault("arithmetic overflow"))
+    }
+
+    fn mul(&self, a: i64, b: i64) -> i64 {
+        a.checked_mul(b).unwrap_or_else(|| self.fault("arithmetic overflow"))
+    }
+
+    // Reads numbers from stdin a line at a time, as the program asks for them.
+    fn read(&mut self) -> i64 {
+        while self.input.is_empty() {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+                self.fault("input closed");
+            }
+            for token in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()) {
+                match token.parse() {
+                    Ok(v) => self.input.push_back(v),
+                    Err(_) => {
+                        eprintln!("bad input {:?}", token);
+                        exit(1)
+                    },
+                }
+            }
+        }
+        self.input.pop_front().unwrap()
+    }
+
+    // the address parameter n refers to, or None in value mode
+    fn target(&self, n: usize) -> Option<usize> {
+        let arg = self.get(self.ip + n + 1);
+        match self.get(self.ip) / [100, 1000, 10000][n] % 10 {
+            0 => Some(self.addr(arg)),
+            1 => None,
+            _ => Some(self.addr(self.add(self.rb, arg))),
+        }
+    }
+
+    fn param(&self, n: usize) -> i64 {
+        match self.target(n) {
+            Some(a) => self.get(a),
+            None => self.get(self.ip + n + 1),
+        }
+    }
+
+    fn dest(&self, n: usize) -> usize {
+        self.target(n).unwrap_or_else(|| self.fault("write parameter in value mode"))
+    }
+
+    // Interprets the instruction at ip.  Used wherever the program had no
+    // instruction to translate, or its instruction has been written over.
+    fn step(&mut self) {
+        let word = self.get(self.ip);
+        if word < 0 {
+            self.fault("unknown opcode");
+        }
+        let mut modes = word / 100;
+        while modes > 0 {
+            if modes % 10 > 2 {
+                self.fault("bad parameter mode");
+            }
+            modes /= 10;
+        }
+        match word % 100 {
+            1 | 2 | 7 | 8 => {
+                let (a, b) = (self.param(0), self.param(1));
+                let v = match word % 100 {
+                    1 => self.add(a, b),
+                    2 => self.mul(a, b),
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                let t = self.dest(2);
+                self.set(t, v);
+                self.ip += 4;
+            },
+            3 => {
+                let t = self.dest(0);
+                let v = self.read();
+                self.set(t, v);
+                self.ip += 2;
+            },
+            4 => {
+                println!("{}", self.param(0));
+                self.ip += 2;
+            },
+            5 | 6 => {
+                if (self.param(0) != 0) == (word % 100 == 5) {
+                    self.ip = self.addr(self.param(1));
+                } else {
+                    self.ip += 3;
+                }
+            },
+            9 => {
+                self.rb = self.add(self.rb, self.param(0));
+                self.ip += 2;
+            },
+            99 => self.halt(),
+            _ => self.fault("unknown opcode"),
+        }
+    }
+}
+"#;
+
+// Translates a program into the source of a standalone Rust program which
+// runs it.  Every address the program has an instruction at becomes a case
+// of the dispatch loop, guarded on the instruction still being there, and
+// anything else is interpreted.  Input is read from stdin as numbers
+// separated by commas or whitespace, each output is printed on its own line,
+// and on halting, cell 0 is reported on stderr.  Arithmetic is checked.
+pub fn transpile(program: &[i64]) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Transpiled from a {} word Intcode program", program.len()).unwrap();
+    out.push_str(PRELUDE);
+
+    writeln!(out, "\nstatic PROGRAM: [i64; {}] = [", program.len()).unwrap();
+    for words in program.chunks(16) {
+        let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        writeln!(out, "    {},", words.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    out.push_str("\nfn main() {\n");
+    out.push_str("    let mut m = Machine { mem: PROGRAM.to_vec(), far: BTreeMap::new(), ip: 0, rb: 0, input: VecDeque::new() };\n");
+    out.push_str("    loop {\n        match m.ip {\n");
+    for addr in 0..program.len() {
+        if let Some((len, body)) = case(program, addr) {
+            let words: Vec<String> = program[addr..addr + len].iter().map(|w| w.to_string()).collect();
+            writeln!(out, "            {} if m.mem[{}..{}] == [{}] => {{", addr, addr, addr + len, words.join(", ")).unwrap();
+            for line in body {
+                writeln!(out, "                {}", line).unwrap();
+            }
+            out.push_str("            },\n");
+        }
+    }
+    out.push_str("            _ => m.step(),\n        }\n    }\n}\n");
+    out
+}
+
+// The length and statements of the case for the instruction at `addr`, if it
+// is one which can run without faulting on how it's written.
+fn case(program: &[i64], addr: usize) -> Option<(usize, Vec<String>)> {
+    let (opcode, modes) = decode_word(program[addr]).ok()?;
+    let len = match opcode {
+        1 | 2 | 7 | 8 => 4,
+        3 | 4 | 9 => 2,
+        5 | 6 => 3,
+        99 => 1,
+        _ => return None,
+    };
+    if addr + len > program.len() {
+        return None;
+    }
+    let arg = |n: usize| program[addr + n + 1];
+    let read = |n: usize| match modes[n] {
+        Mode::Value => Some(arg(n).to_string()),
+        Mode::Pointer if arg(n) < 0 => None,
+        Mode::Pointer => Some(format!("m.get({})", arg(n))),
+        Mode::Relative => Some(format!("m.get(m.addr(m.add(m.rb, {})))", arg(n))),
+    };
+    let dest = |n: usize| match modes[n] {
+        Mode::Value => None,
+        Mode::Pointer if arg(n) < 0 => None,
+        Mode::Pointer => Some(arg(n).to_string()),
+        Mode::Relative => Some(format!("m.addr(m.add(m.rb, {}))", arg(n))),
+    };
+    let next = format!("m.ip = {};", addr + len);
+    let body = match opcode {
+        1 | 2 | 7 | 8 => {
+            let (a, b) = (read(0)?, read(1)?);
+            let value = match opcode {
+                1 => format!("m.add({}, {})", a, b),
+                2 => format!("m.mul({}, {})", a, b),
+                7 => format!("({} < {}) as i64", a, b),
+                _ => format!("({} == {}) as i64", a, b),
+            };
+            vec![
+                format!("let v = {};", value),
+                format!("let t = {};", dest(2)?),
+                "m.set(t, v);".to_string(),
+                next,
+            ]
+        },
+        3 => vec![
+            format!("let t = {};", dest(0)?),
+            "let v = m.read();".to_string(),
+            "m.set(t, v);".to_string(),
+            next,
+        ],
+        4 => vec![format!("println!(\"{{}}\", {});", read(0)?), next],
+        5 | 6 => vec![
+            format!("if {} {} 0 {{", read(0)?, if opcode == 5 { "!=" } else { "==" }),
+            format!("    m.ip = m.addr({});", read(1)?),
+            "} else {".to_string(),
+            format!("    {}", next),
+            "}".to_string(),
+        ],
+        9 => vec![format!("m.rb = m.add(m.rb, {});", read(0)?), next],
+        _ => vec!["m.halt()".to_string()],
+    };
+    Some((len, body))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write as _;
+    use std::process::{Command, Stdio};
+    use crate::intcode::{load_program, BufferIo, IntcodeMachine};
+
+    // Builds the transpiled program with rustc, returning the path of the
+    // executable.
+    fn build(name: &str, program: &[i64]) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("intcode-transpile-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.rs");
+        fs::write(&source, transpile(program)).unwrap();
+        let exe = dir.join("main");
+        let status = Command::new("rustc")
+            .args(["--edition", "2018", "-C", "opt-level=1", "-o"])
+            .arg(&exe)
+            .arg(&source)
+            .status()
+            .expect("failed to run rustc");
+        assert!(status.success(), "transpiled {} doesn't build", name);
+        exe
+    }
+
+    // Runs the executable and an IntcodeMachine on the same input, and checks
+    // that they print the same output and stop in the same way.
+    fn check(exe: &std::path::Path, program: &[i64], input: &[i64]) {
+        let mut child = Command::new(exe)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let text: Vec<String> = input.iter().map(|v| v.to_string()).collect();
+        // a program which never reads may have exited already
+        writeln!(child.stdin.take().unwrap(), "{}", text.join(",")).ok();
+        let run = child.wait_with_output().unwrap();
+
+        let mut mach = IntcodeMachine::with_io(program.to_vec(), BufferIo::new(input.to_vec()));
+        let (status, stopped) = match mach.run_program() {
+            Ok(exit) => (0, format!("halted with {} in cell 0", exit)),
+            Err(e) => (1, e.to_string()),
+        };
+        let output: Vec<String> = mach.io().output.iter().map(|v| format!("{}\n", v)).collect();
+        assert_eq!(output.concat(), String::from_utf8(run.stdout).unwrap(), "{:?}", input);
+        assert_eq!(format!("{}\n", stopped), String::from_utf8(run.stderr).unwrap(), "{:?}", input);
+        assert_eq!(Some(status), run.status.code());
+    }
+
+    #[test]
+    fn test_examples() {
+        let cmp_8 = [3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+                     1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+                     999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
+        let exe = build("cmp8", &cmp_8);
+        for input in [7, 8, 9].iter() {
+            check(&exe, &cmp_8, &[*input]);
+        }
+        // no more input than the one value
+        check(&exe, &cmp_8, &[]);
+
+        let quine = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        check(&build("quine", &quine), &quine, &[]);
+
+        // writes a HLT over the instruction after it
+        let modifies = [1101,0,99,4,1101,0,0,0];
+        check(&build("modifies", &modifies), &modifies, &[]);
+
+        // overflows, then a negative relative address
+        let overflows = [1101,i64::MAX,1,0,99];
+        check(&build("overflows", &overflows), &overflows, &[]);
+        let negative = [109,-5,21101,1,1,0,99];
+        check(&build("negative", &negative), &negative, &[]);
+
+        // the relative base, then an address relative to it, going past i64::MAX
+        let far_base = [109,i64::MAX,109,1,99];
+        check(&build("far_base", &far_base), &far_base, &[]);
+        let far_relative = [109,1,21101,1,1,i64::MAX,99];
+        check(&build("far_relative", &far_relative), &far_relative, &[]);
+
+        // writes and reads back a cell far past the program, then one near it
+        let far = [1101,1,1,1_000_000_000_000,4,1_000_000_000_000,1101,2,2,20,4,20,99];
+        check(&build("far", &far), &far, &[]);
+    }
+
+    #[test]
+    fn test_inputs() {
+        let mut two = load_program("2/input").unwrap();
+        two[1] = 12;
+        two[2] = 2;
+        check(&build("2", &two), &two, &[]);
+
+        let five = load_program("5/input").unwrap();
+        let exe = build("5", &five);
+        check(&exe, &five, &[1]);
+        check(&exe, &five, &[5]);
+
+        let nine = load_program("9/input").unwrap();
+        let exe = build("9", &nine);
+        check(&exe, &nine, &[1]);
+        check(&exe, &nine, &[2]);
+    }
+}
@@ -1,7 +1,12 @@
 use std::env::args;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use intcode::{Arithmetic, AsciiIo, AsciiMachine, BufferIo, Encoding, Run, Search, Slot, IntcodeMachine, Profiler, Snapshot, TraceLog};
 
 mod intcode;
+mod bench;
 mod point;
 
 mod one;
@@ -19,6 +24,212 @@ mod twelve;
 mod thirteen;
 mod fourteen;
 mod fifteen;
+mod seventeen;
+mod nineteen;
+mod twentyone;
+mod twentythree;
+mod twentyfive;
+
+// Reads the whole of the named file, or of stdin if there is no name.
+fn read_file_or<I: BufRead>(mut buf: I, path: Option<&String>) -> String {
+    let mut text = String::new();
+    match path {
+        Some(path) => {
+            text = fs::read_to_string(path).expect("failed to read file");
+        },
+        None => {
+            buf.read_to_string(&mut text).expect("failed to read stdin");
+        },
+    }
+    text
+}
+
+// Runs a program or a saved snapshot of one, talking ASCII over stdin and
+// stdout a line at a time.  The program comes from a file so that stdin is
+// free for text.  Output which isn't text is shown as [value].  If a save
+// path is given and stdin runs out while the program is waiting for input,
+// the machine is saved there so that it can be resumed later.  With --raw,
+// stdin and stdout are instead passed to and from the program a byte at a
+// time, for programs which prompt without finishing the line.
+fn run_ascii<I: BufRead>(buf: I, args: &[String]) {
+    if args.is_empty() {
+        println!("usage: ascii [--raw] <program|snapshot> [save-to]");
+        return;
+    }
+    if args[0] == "--raw" {
+        let path = args.get(1).expect("usage: ascii --raw <program>");
+        let text = fs::read_to_string(path).expect("failed to read program");
+        let program = intcode::read_program(text.as_bytes()).unwrap();
+        let mut mach = IntcodeMachine::with_io(program, AsciiIo::new(buf, io::stdout()));
+        if let Err(e) = mach.run_program() {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    let bytes = fs::read(&args[0]).expect("failed to read program");
+    let mut mach = if bytes.starts_with(b"ip ") {
+        AsciiMachine::resume(&Snapshot::read(&bytes[..]).unwrap())
+    } else {
+        AsciiMachine::new(intcode::read_program(&bytes[..]).unwrap())
+    };
+
+    let mut commands = buf.lines();
+    loop {
+        let res = match mach.run() {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            },
+        };
+        for line in res.lines.iter() {
+            println!("{}", line);
+        }
+        for v in res.values.iter() {
+            println!("[{}]", v);
+        }
+        if res.halted {
+            return;
+        }
+        match (commands.next(), args.get(1)) {
+            (Some(Ok(command)), _) => mach.send(&format!("{}\n", command)),
+            (_, Some(save)) => {
+                let mut file = File::create(save).expect("failed to create snapshot");
+                write!(file, "{}", mach.snapshot()).expect("failed to write snapshot");
+                eprintln!("saved to {}", save);
+                return;
+            },
+            (_, None) => return,
+        }
+    }
+}
+
+// Runs a program with the given inputs, either logging every instruction
+// executed or, when profiling, reporting where the time went.  Output from
+// the program is printed once it halts.  The arithmetic used can be chosen
+// with --arith=.
+fn run_traced(mut args: &[String], profile: bool) {
+    let mut arithmetic = Arithmetic::default();
+    if let Some(policy) = args.first().and_then(|a| a.strip_prefix("--arith=")) {
+        arithmetic = policy.parse().expect("arithmetic must be wrapping, checked or widened");
+        args = &args[1..];
+    }
+    if args.is_empty() {
+        println!("usage: trace|profile [--arith=wrapping|checked|widened] <program> [input...]");
+        return;
+    }
+    let program = intcode::load_program(&args[0]).unwrap();
+    let input: Vec<i64> = args[1..].iter()
+        .map(|a| a.parse().expect("inputs must be numbers"))
+        .collect();
+    let mut mach = IntcodeMachine::with_arithmetic(program, BufferIo::new(input), arithmetic);
+
+    let profiler = Arc::new(Mutex::new(Profiler::default()));
+    let log = Arc::new(Mutex::new(TraceLog::new(io::stdout())));
+    if profile {
+        mach.trace(profiler.clone());
+    } else {
+        mach.trace(log.clone());
+    }
+    if let Err(e) = mach.run_program() {
+        eprintln!("{}", e);
+    }
+    if let Some(e) = log.lock().unwrap().error() {
+        eprintln!("failed to write trace: {}", e);
+        return;
+    }
+    let output: Vec<String> = mach.io_mut().output.iter().map(|o| o.to_string()).collect();
+    println!("output: {}", output.join(","));
+    if profile {
+        profiler.lock().unwrap().report(io::stdout(), 20);
+    }
+}
+
+// Runs a program with the given inputs and reports how it used its memory,
+// followed by a listing which uses that to tell code from data.
+fn run_map(args: &[String]) {
+    if args.is_empty() {
+        println!("usage: map <program> [input...]");
+        return;
+    }
+    let program = intcode::load_program(&args[0]).unwrap();
+    let input: Vec<i64> = args[1..].iter()
+        .map(|a| a.parse().expect("inputs must be numbers"))
+        .collect();
+    match intcode::analyse(program.to_owned(), input) {
+        Ok(map) => {
+            map.report(io::stdout());
+            println!();
+            print!("{}", intcode::disassemble_map(&program, &map));
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+// Searches for values of memory cells and inputs which make a program halt
+// with a given value in cell 0, or with a given last output.  Cells are
+// written [addr]=lo..hi and inputs inN=lo..hi, for the Nth input; input=a,b,c
+// gives input which every run starts from.  Prints the first solution, or
+// every one if `all` is given.
+fn run_search(args: &[String]) {
+    let usage = "usage: search <program> exit=N|out=N [all] [input=a,b,...] <[addr]|inN>=lo..hi...";
+    if args.is_empty() {
+        println!("{}", usage);
+        return;
+    }
+    let program = intcode::load_program(&args[0]).unwrap();
+    let mut search = Search::new(program);
+    search.parallel(thread::available_parallelism().map_or(1, |n| n.get()));
+    let mut target = None;
+    let mut all = false;
+    let number = |n: &str| -> i64 { n.parse().expect("not a number") };
+    for arg in args[1..].iter() {
+        let (name, value) = match arg.split_once('=') {
+            Some(pair) => pair,
+            None if arg == "all" => {
+                all = true;
+                continue;
+            },
+            None => panic!("{}", usage),
+        };
+        match name {
+            "exit" | "out" => target = Some((name == "exit", number(value))),
+            "input" => search.input(value.split(',').map(number).collect()),
+            _ => {
+                let (lo, hi) = value.split_once("..").expect("ranges are lo..hi");
+                let slot = match name.strip_prefix("in") {
+                    Some(n) => n.parse().map(Slot::Input),
+                    None => name.trim_start_matches('[').trim_end_matches(']').parse().map(Slot::Cell),
+                };
+                let slot = match slot {
+                    Ok(slot) => slot,
+                    Err(_) => {
+                        println!("bad address or input number in {:?}", name);
+                        return;
+                    },
+                };
+                if let Err(e) = search.vary(slot, number(lo)..=number(hi)) {
+                    println!("{}", e);
+                    return;
+                }
+            },
+        }
+    }
+    let (exit, goal) = target.expect(usage);
+    let accept = |run: &Run| if exit { run.exit == goal } else { run.output.last() == Some(&goal) };
+    let found = if all {
+        search.all(accept)
+    } else {
+        search.first(accept).into_iter().collect()
+    };
+    if found.is_empty() {
+        println!("no solution");
+    }
+    for solution in found {
+        let values: Vec<String> = solution.values.iter().map(|v| v.to_string()).collect();
+        println!("{}", values.join(" "));
+    }
+}
 
 fn main() {
     let args: Vec<_> = args().collect();
@@ -29,7 +240,10 @@ fn main() {
             "1a" => println!("{}", one::one_a(buf)),
             "1b" => println!("{}", one::one_b(buf)),
             "2a" => println!("{}", two::two_a(buf)),
-            "2b" => println!("{:?}", two::two_b(buf)),
+            "2b" => match two::two_b(buf) {
+                Some(answer) => println!("{}", answer),
+                None => println!("no noun and verb give the needle"),
+            },
             "3a" => println!("{}", three::three_a(buf)),
             "3b" => println!("{}", three::three_b(buf)),
             "4a" => println!("{}", four::four_a(buf)),
@@ -60,6 +274,79 @@ fn main() {
             "14b" => println!("{}", fourteen::fourteen_b(buf)),
             "15a" => println!("{}", fifteen::fifteen_a(buf)),
             "15b" => println!("{}", fifteen::fifteen_b(buf)),
+            "17a" => println!("{}", seventeen::seventeen_a(buf)),
+            "17b" => println!("{}", seventeen::seventeen_b(buf)),
+            "19a" => println!("{}", nineteen::nineteen_a(buf)),
+            "19b" => println!("{}", nineteen::nineteen_b(buf)),
+            "21a" => println!("{}", twentyone::twentyone_a(buf)),
+            "21b" => println!("{}", twentyone::twentyone_b(buf)),
+            "23a" => println!("{}", twentythree::twentythree_a(buf)),
+            "23b" => println!("{}", twentythree::twentythree_b(buf)),
+            "25a" => println!("{}", twentyfive::twentyfive_a(buf, args.get(2))),
+            "25r" => {
+                let path = args.get(2).expect("usage: 25r <transcript>");
+                twentyfive::twentyfive_replay(buf, path);
+            }
+            "ascii" => run_ascii(buf, &args[2..]),
+            "dis" => {
+                let program = match args.get(2) {
+                    Some(path) => intcode::load_program(path),
+                    None => intcode::read_program(buf),
+                };
+                match program {
+                    Ok(p) => print!("{}", intcode::disassemble(&p)),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            "debug" => {
+                // the program comes from a file so that stdin is free for commands
+                let path = args.get(2).expect("usage: debug <program>");
+                let res = intcode::load_program(path)
+                    .and_then(|p| intcode::Debugger::new(p).repl(buf, io::stdout()));
+                if let Err(e) = res {
+                    eprintln!("{}", e);
+                }
+            }
+            "pack" => {
+                // writes the program in binary, to stdout
+                let path = args.get(2).expect("usage: pack <program> [words|varint]");
+                let encoding = args.get(3).map_or(Encoding::Varint, |e| e.parse().unwrap());
+                match intcode::load_program(path) {
+                    Ok(p) => io::stdout().write_all(&intcode::encode_program(&p, encoding))
+                                         .expect("failed to write program"),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            "transpile" => {
+                // writes a standalone Rust program running the Intcode one, to stdout
+                let path = args.get(2).expect("usage: transpile <program>");
+                match intcode::load_program(path) {
+                    Ok(p) => print!("{}", intcode::transpile(&p)),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            "bench" => {
+                let rounds = args.get(2).map_or(10, |r| r.parse().expect("rounds must be a number"));
+                if rounds == 0 {
+                    eprintln!("rounds must be at least 1");
+                    return;
+                }
+                bench::bench(args.get(3).map_or(".", |r| r.as_str()), rounds);
+            }
+            "trace" => run_traced(&args[2..], false),
+            "profile" => run_traced(&args[2..], true),
+            "map" => run_map(&args[2..]),
+            "search" => run_search(&args[2..]),
+            "asm" => {
+                let text = read_file_or(buf, args.get(2));
+                match intcode::assemble(&text) {
+                    Ok(p) => {
+                        let words: Vec<String> = p.iter().map(|w| w.to_string()).collect();
+                        println!("{}", words.join(","));
+                    },
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
             _ => println!("argument unrecognized: {}", args[1]),
         }
     }
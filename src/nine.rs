@@ -1,22 +1,25 @@
 use std::io::BufRead;
-use std::thread;
-use crate::intcode::IntcodeMachine;
+use crate::intcode::{FnIo, IntcodeMachine};
 use crate::intcode;
 
 
+// runs the BOOST program in the given mode, collecting its outputs
+fn run_boost(p: Vec<i64>, mode: i64) -> Vec<i64> {
+    let mut res = Vec::new();
+    let io = FnIo::new(|| Some(mode), |o| res.push(o));
+    let mut mach = IntcodeMachine::with_io(p, io);
+    mach.run_program().unwrap();
+    drop(mach);
+    res
+}
+
 pub fn nine_a<I>(buf: I) -> i64
 where
     I: BufRead,
 {
 
-    let p = intcode::read_program(buf);
-    let (m_in, m_out, mut mach) = IntcodeMachine::new(p);
-
-    m_in.send(1).unwrap();
-    thread::spawn(move || {
-        mach.run_program();
-    });
-    m_out.recv().unwrap()
+    let p = intcode::read_program(buf).unwrap();
+    run_boost(p, 1)[0]
 }
 
 pub fn nine_b<I>(buf: I) -> Vec<i64>
@@ -24,17 +27,8 @@ where
     I: BufRead,
 {
 
-    let p = intcode::read_program(buf);
-    let (m_in, m_out, mut mach) = IntcodeMachine::new(p);
-
-    m_in.send(2).unwrap();
-    thread::spawn(move || {
-        mach.run_program();
-    });
-    let mut res = Vec::new();
-    for o in m_out {
-        res.push(o);
-    }
+    let p = intcode::read_program(buf).unwrap();
+    let mut res = run_boost(p, 2);
     res.reverse();
     res
 }
@@ -0,0 +1,206 @@
+use std::cmp::{max, min};
+use std::io::BufRead;
+use crate::intcode;
+use crate::intcode::{BufferIo, IntcodeError, IntcodeMachine, StopReason};
+
+const SCAN_SIZE: i64 = 50;
+const SHIP_SIZE: i64 = 100;
+
+// How far right of the last row's beam to look for this row's.  The beam
+// never moves further than this from one row to the next.
+const SLOPE: i64 = 10;
+
+// rows to give up after when looking for somewhere a square fits
+const MAX_ROWS: i64 = 100_000;
+
+// The beam in one row: every x from start up to but not including end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Span {
+    fn len(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+// A drone program, asked about one point at a time.  The program halts after
+// each answer, so every query runs on a copy of a machine which has already
+// got as far as reading its first input.
+pub struct Beam {
+    ready: IntcodeMachine<BufferIo>,
+}
+
+impl Beam {
+    pub fn new(program: Vec<i64>) -> Result<Beam, IntcodeError> {
+        let mut ready = IntcodeMachine::with_io(program, BufferIo::default());
+        ready.run_until(None)?;
+        Ok(Beam { ready })
+    }
+
+    pub fn affected(&self, x: i64, y: i64) -> Result<bool, IntcodeError> {
+        let mut drone = self.ready.clone();
+        drone.io_mut().input.extend(&[x, y]);
+        match drone.run_until(None)? {
+            StopReason::Output(v) => Ok(v == 1),
+            _ => Err(IntcodeError::Io(format!("no report from the drone at ({}, {})", x, y))),
+        }
+    }
+
+    // Finds the beam in row `y`, left of `width`.  The beam is a cone from the
+    // origin, so both its edges only move right from row to row; `last` is the
+    // nearest row above which had any beam, and only the cells around its
+    // edges are asked about.  Rows near the origin may have no beam at all.
+    pub fn span(&self, y: i64, last: Option<Span>, width: i64) -> Result<Option<Span>, IntcodeError> {
+        let (from, to) = match last {
+            Some(last) => (last.start, last.end + SLOPE),
+            None => (0, SLOPE * (y + 1)),
+        };
+        let mut x = from;
+        while x < min(to, width) && !self.affected(x, y)? {
+            x += 1;
+        }
+        if x >= min(to, width) {
+            return Ok(None);
+        }
+
+        let start = x;
+        let mut end = max(start + 1, last.map_or(0, |l| min(l.end, width)));
+        while end < width && self.affected(end, y)? {
+            end += 1;
+        }
+        Ok(Some(Span { start, end }))
+    }
+
+    // the beam in each row of the size by size square at the origin
+    pub fn map(&self, size: i64) -> Result<Vec<Option<Span>>, IntcodeError> {
+        let mut rows = Vec::new();
+        let mut last = None;
+        for y in 0..size {
+            let span = self.span(y, last, size)?;
+            last = span.or(last);
+            rows.push(span);
+        }
+        Ok(rows)
+    }
+
+    // The top left corner of the square nearest the origin which is n wide
+    // and fits wholly in the beam.  A square whose bottom left corner is at
+    // the start of row y fits if the row n-1 above reaches far enough right.
+    // There is no square less than 1 wide.
+    pub fn fit(&self, n: i64) -> Result<Option<(i64, i64)>, IntcodeError> {
+        if n < 1 {
+            return Ok(None);
+        }
+        let mut rows: Vec<Option<Span>> = Vec::new();
+        let mut last = None;
+        for y in 0..MAX_ROWS {
+            let span = self.span(y, last, i64::MAX)?;
+            last = span.or(last);
+            rows.push(span);
+
+            let top = y - (n - 1);
+            if let (Some(bottom), true) = (span, top >= 0) {
+                if let Some(above) = rows[top as usize] {
+                    if bottom.len() >= n && above.start <= bottom.start && above.end >= bottom.start + n {
+                        return Ok(Some((bottom.start, top)));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub fn nineteen_a<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let beam = Beam::new(p).unwrap();
+    beam.map(SCAN_SIZE).unwrap().iter().flatten().map(|s| s.len()).sum()
+}
+
+pub fn nineteen_b<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let beam = Beam::new(p).unwrap();
+    let (x, y) = beam.fit(SHIP_SIZE).unwrap().expect("the ship never fits");
+    x * 10000 + y
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::assemble;
+
+    // a beam between the lines 3x = 2y and x = 2y, as a drone program
+    const DRONE: &str = "
+            in [x]
+            in [y]
+            mul [x], #3, [a]
+            mul [y], #2, [b]
+            mul [y], #6, [c]
+            lt [a], [b], [t]
+            jt [t], #miss
+            lt [c], [a], [t]
+            jt [t], #miss
+            out #1
+            hlt
+    miss:   out #0
+            hlt
+    x:      db 0
+    y:      db 0
+    a:      db 0
+    b:      db 0
+    c:      db 0
+    t:      db 0
+    ";
+
+    fn inside(x: i64, y: i64) -> bool {
+        2 * y <= 3 * x && 3 * x <= 6 * y
+    }
+
+    #[test]
+    fn test_affected() {
+        let beam = Beam::new(assemble(DRONE).unwrap()).unwrap();
+        for y in 0..10 {
+            for x in 0..10 {
+                assert_eq!(Ok(inside(x, y)), beam.affected(x, y), "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let beam = Beam::new(assemble(DRONE).unwrap()).unwrap();
+        let rows = beam.map(SCAN_SIZE).unwrap();
+        for (y, span) in rows.iter().enumerate() {
+            let xs: Vec<i64> = (0..SCAN_SIZE).filter(|x| inside(*x, y as i64)).collect();
+            let expected = xs.first().map(|s| Span { start: *s, end: xs.last().unwrap() + 1 });
+            assert_eq!(expected, *span, "row {}", y);
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        let beam = Beam::new(assemble(DRONE).unwrap()).unwrap();
+        for n in 1..8 {
+            let fits = |x: i64, y: i64| (0..n).all(|dy| (0..n).all(|dx| inside(x + dx, y + dy)));
+            let nearest = (0..200)
+                .flat_map(|y| (0..200).map(move |x| (x, y)))
+                .filter(|(x, y)| fits(*x, *y))
+                .min_by_key(|(x, y)| (x + y, *y));
+            let (x, y) = beam.fit(n).unwrap().unwrap();
+            assert!(fits(x, y), "{} square at ({}, {})", n, x, y);
+            assert_eq!(nearest.map(|(x, y)| x + y), Some(x + y), "{} square", n);
+        }
+        assert_eq!(Ok(None), beam.fit(0));
+        assert_eq!(Ok(None), beam.fit(-3));
+    }
+}
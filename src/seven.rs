@@ -1,83 +1,28 @@
 use std::io::BufRead;
-use std::thread;
 use crate::intcode;
-use crate::intcode::IntcodeMachine;
+use crate::intcode::{IntcodeError, Network, Outcome};
 
+// Runs five amplifiers in a loop, each first given its phase, and returns
+// the last signal out of the final amplifier, or None if they deadlock.
 fn amp_chain_feedback(
     program: Vec<i64>,
     phases: Vec<i64>,
-) -> i64 {
-
-    let (a_in, a_out, mut amp_a) = IntcodeMachine::new(program.to_owned());
-    let (b_in, b_out, mut amp_b) = IntcodeMachine::new(program.to_owned());
-    let (c_in, c_out, mut amp_c) = IntcodeMachine::new(program.to_owned());
-    let (d_in, d_out, mut amp_d) = IntcodeMachine::new(program.to_owned());
-    let (e_in, e_out, mut amp_e) = IntcodeMachine::new(program.to_owned());
-
-    a_in.send(phases[0]).unwrap();
-    b_in.send(phases[1]).unwrap();
-    c_in.send(phases[2]).unwrap();
-    d_in.send(phases[3]).unwrap();
-    e_in.send(phases[4]).unwrap();
-
-    a_in.send(0).unwrap();
-
-    thread::spawn(move || {
-        amp_a.run_program();
-    });
-    thread::spawn(move || {
-        for i in a_out {
-            b_in.send(i).unwrap();
-        }
-    });
-
-    thread::spawn(move || {
-        amp_b.run_program();
-    });
-    thread::spawn(move || {
-        for i in b_out {
-            c_in.send(i).unwrap();
-        }
-    });
-
-    thread::spawn(move || {
-        amp_c.run_program();
-    });
-    thread::spawn(move || {
-        for i in c_out {
-            d_in.send(i).unwrap();
-        }
-    });
-
-    thread::spawn(move || {
-        amp_d.run_program();
-    });
-    thread::spawn(move || {
-        for i in d_out {
-            e_in.send(i).unwrap();
-        }
-    });
-
-    thread::spawn(move || {
-        amp_e.run_program();
-    });
-    let final_out = thread::spawn(move || {
-        let mut result: i64 = -1;
-        for i in e_out {
-            match a_in.send(i) {
-                Ok(_) => {
-                    continue;
-                },
-                Err(_) => {
-                    result = i;
-                    break;
-                },
-            }
-        }
-        result
-    });
+) -> Result<Option<i64>, IntcodeError> {
 
-    final_out.join().unwrap()
+    let mut amps = Network::default();
+    for phase in phases.iter() {
+        let amp = amps.add(program.to_owned());
+        amps.input(amp, *phase);
+    }
+    for amp in 0..phases.len() {
+        amps.connect(amp, (amp + 1) % phases.len());
+    }
+    amps.input(0, 0);
+
+    match amps.run()? {
+        Outcome::Halted => Ok(Some(*amps.outputs(phases.len() - 1).last().unwrap_or(&-1))),
+        Outcome::Deadlock(_) => Ok(None),
+    }
 }
 
 fn heaps(n: usize, a: &mut Vec<i64>) -> Vec<Vec<i64>> {
@@ -104,14 +49,22 @@ pub fn seven_a<I>(buf: I) -> i64
 where
     I: BufRead,
 {
-    let p = intcode::read_program(buf);
+    let p = intcode::read_program(buf).unwrap();
     let mut phases = vec![0,1,2,3,4];
-    let mut o = 0;
-    let mut max = o;
+    let mut max = 0;
     for v in heaps(5, &mut phases).iter() {
-        o = amp_chain_feedback(p.to_owned(), v.to_vec());
-        if o > max {
-            max = o;
+        match amp_chain_feedback(p.to_owned(), v.to_vec()) {
+            Ok(Some(o)) => {
+                if o > max {
+                    max = o;
+                }
+            },
+            Ok(None) => {
+                eprintln!("phases {:?} deadlocked", v);
+            },
+            Err(e) => {
+                eprintln!("phases {:?} failed: {}", v, e);
+            },
         }
     }
     max
@@ -121,14 +74,22 @@ pub fn seven_b<I>(buf: I) -> i64
 where
     I: BufRead,
 {
-    let p = intcode::read_program(buf);
+    let p = intcode::read_program(buf).unwrap();
     let mut phases = vec![5,6,7,8,9];
-    let mut o = 0;
-    let mut max = o;
+    let mut max = 0;
     for v in heaps(5, &mut phases).iter() {
-        o = amp_chain_feedback(p.to_owned(), v.to_vec());
-        if o > max {
-            max = o;
+        match amp_chain_feedback(p.to_owned(), v.to_vec()) {
+            Ok(Some(o)) => {
+                if o > max {
+                    max = o;
+                }
+            },
+            Ok(None) => {
+                eprintln!("phases {:?} deadlocked", v);
+            },
+            Err(e) => {
+                eprintln!("phases {:?} failed: {}", v, e);
+            },
         }
     }
     max
@@ -160,4 +121,21 @@ mod tests {
         let program = b"3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10";
         assert_eq!(seven_b(&program[..]), 18216);
     }
+
+    #[test]
+    fn test_feedback_deadlock() {
+        let program = intcode::read_program(&b"3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"[..]).unwrap();
+        assert_eq!(Ok(Some(139629729)), amp_chain_feedback(program.to_owned(), vec![9,8,7,6,5]));
+
+        // with no first signal for the loop every amp waits on its neighbor
+        let mut amps = Network::default();
+        for phase in [9,8,7,6,5].iter() {
+            let amp = amps.add(program.to_owned());
+            amps.input(amp, *phase);
+        }
+        for amp in 0..5 {
+            amps.connect(amp, (amp + 1) % 5);
+        }
+        assert_eq!(Ok(Outcome::Deadlock(vec![0,1,2,3,4])), amps.run());
+    }
 }
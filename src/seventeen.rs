@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::BufRead;
+use crate::intcode;
+use crate::intcode::{AsciiMachine, IntcodeError};
+use crate::point::Point;
+
+// longest a movement routine may be, in characters, not counting the newline
+const ROUTINE_BUDGET: usize = 20;
+const ROUTINE_NAMES: [char; 3] = ['A', 'B', 'C'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Heading {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Heading {
+    fn left(self) -> Heading {
+        match self {
+            Heading::Up => Heading::Left,
+            Heading::Left => Heading::Down,
+            Heading::Down => Heading::Right,
+            Heading::Right => Heading::Up,
+        }
+    }
+
+    fn right(self) -> Heading {
+        self.left().left().left()
+    }
+
+    fn step(self, p: &Point) -> Point {
+        match self {
+            Heading::Up => p.up(),
+            Heading::Down => p.down(),
+            Heading::Left => p.left(),
+            Heading::Right => p.right(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tile {
+    Scaffold,
+    Open,
+    // the robot, which is always on scaffold
+    Robot(Heading),
+    // the robot, tumbling through space
+    Tumbling,
+}
+
+// One frame from the cameras.  Rows go down the screen and y goes up it, so
+// the tile in row r, column c is at (c, -r).
+pub struct View {
+    pub tiles: HashMap<Point, Tile>,
+}
+
+impl View {
+    // Parses the first frame in `lines`, which ends at the first blank line.
+    pub fn parse(lines: &[String]) -> View {
+        let mut tiles = HashMap::new();
+        for (row, line) in lines.iter().take_while(|l| !l.is_empty()).enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let tile = match c {
+                    '#' => Tile::Scaffold,
+                    '^' => Tile::Robot(Heading::Up),
+                    'v' => Tile::Robot(Heading::Down),
+                    '<' => Tile::Robot(Heading::Left),
+                    '>' => Tile::Robot(Heading::Right),
+                    'X' => Tile::Tumbling,
+                    _ => Tile::Open,
+                };
+                tiles.insert(Point { x: col as i32, y: -(row as i32) }, tile);
+            }
+        }
+        View { tiles }
+    }
+
+    fn scaffold(&self, p: &Point) -> bool {
+        matches!(self.tiles.get(p), Some(Tile::Scaffold) | Some(Tile::Robot(_)))
+    }
+
+    pub fn robot(&self) -> Option<(Point, Heading)> {
+        self.tiles.iter().find_map(|(p, t)| match t {
+            Tile::Robot(h) => Some((p.clone(), *h)),
+            _ => None,
+        })
+    }
+
+    // scaffold with scaffold on all four sides
+    pub fn intersections(&self) -> Vec<Point> {
+        self.tiles.keys()
+            .filter(|p| {
+                self.scaffold(p)
+                    && self.scaffold(&p.up())
+                    && self.scaffold(&p.down())
+                    && self.scaffold(&p.left())
+                    && self.scaffold(&p.right())
+            })
+            .cloned()
+            .collect()
+    }
+
+    // The way along the scaffold from the robot to its far end, going straight
+    // on wherever possible, and otherwise turning whichever way it goes.  A
+    // scaffold which closes into a loop is followed once round.
+    pub fn path(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let (mut at, mut heading) = match self.robot() {
+            Some(robot) => robot,
+            None => return moves,
+        };
+        let mut seen = HashSet::new();
+        loop {
+            // only at the start can the scaffold carry straight on, or lie
+            // behind the robot
+            let turns: &[Move] = if self.scaffold(&heading.step(&at)) {
+                &[]
+            } else if self.scaffold(&heading.left().step(&at)) {
+                &[Move::Left]
+            } else if self.scaffold(&heading.right().step(&at)) {
+                &[Move::Right]
+            } else if moves.is_empty() && self.scaffold(&heading.left().left().step(&at)) {
+                &[Move::Right, Move::Right]
+            } else {
+                return moves;
+            };
+            for turn in turns {
+                heading = if *turn == Move::Left { heading.left() } else { heading.right() };
+            }
+            if !seen.insert((at.clone(), heading)) {
+                return moves;
+            }
+            moves.extend_from_slice(turns);
+            let mut n = 0;
+            while self.scaffold(&heading.step(&at)) {
+                at = heading.step(&at);
+                n += 1;
+            }
+            moves.push(Move::Forward(n));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Move {
+    Left,
+    Right,
+    Forward(usize),
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Move::Left => write!(f, "L"),
+            Move::Right => write!(f, "R"),
+            Move::Forward(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+// a routine as the robot reads it, e.g. R,8,L,10
+pub fn render(moves: &[Move]) -> String {
+    let words: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+    words.join(",")
+}
+
+// A path split into a main routine calling up to three movement functions
+#[derive(Debug, PartialEq)]
+pub struct Routines {
+    // which function to call, in order, as indexes into `functions`
+    pub main: Vec<usize>,
+    pub functions: Vec<Vec<Move>>,
+}
+
+impl Routines {
+    // the input the robot expects: main routine, then each function, then
+    // whether to show the video feed
+    pub fn input(&self) -> String {
+        let calls: Vec<String> = self.main.iter().map(|f| ROUTINE_NAMES[*f].to_string()).collect();
+        let mut input = calls.join(",") + "\n";
+        for i in 0..ROUTINE_NAMES.len() {
+            let function = self.functions.get(i).map_or(String::new(), |f| render(f));
+            input += &function;
+            input += "\n";
+        }
+        input + "n\n"
+    }
+}
+
+// Splits `path` into a main routine and functions, each of which must render
+// to no more than `budget` characters.
+pub fn compress(path: &[Move], budget: usize) -> Option<Routines> {
+    let mut routines = Routines { main: Vec::new(), functions: Vec::new() };
+    if compress_rest(path, budget, &mut routines) {
+        Some(routines)
+    } else {
+        None
+    }
+}
+
+fn compress_rest(rest: &[Move], budget: usize, routines: &mut Routines) -> bool {
+    if rest.is_empty() {
+        return true;
+    }
+    // each call takes a letter and a comma, bar the last
+    if 2 * (routines.main.len() + 1) - 1 > budget {
+        return false;
+    }
+    for f in 0..routines.functions.len() {
+        if rest.starts_with(&routines.functions[f]) {
+            routines.main.push(f);
+            if compress_rest(&rest[routines.functions[f].len()..], budget, routines) {
+                return true;
+            }
+            routines.main.pop();
+        }
+    }
+    if routines.functions.len() < ROUTINE_NAMES.len() {
+        for len in 1..=rest.len() {
+            if render(&rest[..len]).len() > budget {
+                break;
+            }
+            routines.functions.push(rest[..len].to_vec());
+            routines.main.push(routines.functions.len() - 1);
+            if compress_rest(&rest[len..], budget, routines) {
+                return true;
+            }
+            routines.main.pop();
+            routines.functions.pop();
+        }
+    }
+    false
+}
+
+fn capture(program: Vec<i64>) -> Result<View, IntcodeError> {
+    let mut mach = AsciiMachine::new(program);
+    let res = mach.run()?;
+    Ok(View::parse(&res.lines))
+}
+
+pub fn seventeen_a<I>(buf: I) -> i32
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let view = capture(p).unwrap();
+    view.intersections().iter().map(|p| p.x * -p.y).sum()
+}
+
+pub fn seventeen_b<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    let mut p = intcode::read_program(buf).unwrap();
+    let view = capture(p.to_owned()).unwrap();
+    let routines = compress(&view.path(), ROUTINE_BUDGET).expect("path doesn't compress");
+
+    // wake the robot up, so that it asks where to go
+    p[0] = 2;
+    let mut mach = AsciiMachine::new(p);
+    mach.send(&routines.input());
+    let res = mach.run().unwrap();
+    *res.values.last().expect("robot reported no dust")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(text: &str) -> View {
+        let lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+        View::parse(&lines)
+    }
+
+    #[test]
+    fn test_intersections() {
+        let view = view("\
+..#..........
+..#..........
+#######...###
+#.#...#...#.#
+#############
+..#...#...#..
+..#####...^..
+");
+        let mut crossings: Vec<(i32, i32)> = view.intersections().iter().map(|p| (p.x, -p.y)).collect();
+        crossings.sort();
+        assert_eq!(vec![(2,2), (2,4), (6,4), (10,4)], crossings);
+        assert_eq!(Some((Point { x: 10, y: -6 }, Heading::Up)), view.robot());
+    }
+
+    const LOOPS: &str = "\
+#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......
+";
+
+    #[test]
+    fn test_path() {
+        assert_eq!("R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2",
+                   render(&view(LOOPS).path()));
+        // facing along the scaffold, the robot sets off without turning
+        let facing = LOOPS.replace("^###", ">###");
+        assert_eq!("8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2",
+                   render(&view(&facing).path()));
+
+        // a closed loop is followed once round
+        let ring = "#####\n#...#\n^...#\n#...#\n#####\n";
+        assert_eq!("2,R,4,R,4,R,4,R,4", render(&view(ring).path()));
+        // the only scaffold is behind the robot
+        assert_eq!("R,R,2", render(&view("^\n#\n#\n").path()));
+        assert_eq!("", render(&view("^\n").path()));
+    }
+
+    #[test]
+    fn test_compress() {
+        let path = view(LOOPS).path();
+        let routines = compress(&path, ROUTINE_BUDGET).unwrap();
+        let expanded: Vec<Move> = routines.main.iter()
+            .flat_map(|f| routines.functions[*f].iter().cloned())
+            .collect();
+        assert_eq!(path, expanded);
+        assert!(routines.functions.len() <= 3);
+        for line in routines.input().lines() {
+            assert!(line.len() <= ROUTINE_BUDGET, "{:?} is too long", line);
+        }
+
+        // too tight a budget can't be met
+        assert_eq!(None, compress(&path, 6));
+    }
+
+    #[test]
+    fn test_input() {
+        let routines = Routines {
+            main: vec![0, 1, 0],
+            functions: vec![vec![Move::Right, Move::Forward(8)], vec![Move::Left, Move::Forward(10)]],
+        };
+        assert_eq!("A,B,A\nR,8\nL,10\n\nn\n", routines.input());
+    }
+}
@@ -90,10 +90,10 @@ where
     I: BufRead,
 {
 
-    let program = intcode::read_program(buf);
+    let program = intcode::read_program(buf).unwrap();
     let (_, m_out, mut mach) = IntcodeMachine::new(program);
     thread::spawn(move || {
-        mach.run_program();
+        mach.run_program().unwrap();
     });
 
     let mut count = 0;
@@ -117,11 +117,11 @@ where
     I: BufRead,
 {
 
-    let mut program = intcode::read_program(buf);
+    let mut program = intcode::read_program(buf).unwrap();
     program[0] = 2;  // free play!
     let (m_in, m_out, mut mach) = IntcodeMachine::new(program);
     thread::spawn(move || {
-        mach.run_program();
+        mach.run_program().unwrap();
     });
     let (x_sender, x_receiver) = channel();
     let game = thread::spawn(move || {
@@ -178,7 +178,7 @@ pub fn play_interactive<I>(buf: I)
 where
     I: BufRead,
 {
-    let mut program = intcode::read_program(buf);
+    let mut program = intcode::read_program(buf).unwrap();
     program[0] = 2;  // free play!
     let (m_in, m_out, mut mach) = IntcodeMachine::new(program);
     let tty = fs::OpenOptions::new().read(true)
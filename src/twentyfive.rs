@@ -0,0 +1,516 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::BufRead;
+use crate::intcode;
+use crate::intcode::{AsciiMachine, IntcodeError, Response};
+
+// most instructions any one command may take before the game is taken to
+// have locked up, as it does when some items are picked up
+const STEP_LIMIT: usize = 1_000_000;
+
+// Something the game can be played on: sends a command (or nothing, to see
+// how the game starts) and returns the game's reply, or None if it stopped
+// responding.
+pub trait Console: Clone {
+    fn command(&mut self, command: &str) -> Result<Option<Response>, IntcodeError>;
+}
+
+impl Console for AsciiMachine {
+    fn command(&mut self, command: &str) -> Result<Option<Response>, IntcodeError> {
+        if !command.is_empty() {
+            self.send(&format!("{}\n", command));
+        }
+        let res = self.run_for(STEP_LIMIT)?;
+        Ok(if res.limit_reached { None } else { Some(res) })
+    }
+}
+
+#[derive(Debug)]
+pub enum AdventureError {
+    Machine(IntcodeError),
+    // the game locked up after the given command
+    Stalled(String),
+    // the reply to the given command didn't describe a room
+    Lost(String),
+    NoCheckpoint,
+    // no set of the items carried gets past the checkpoint
+    TooHeavy,
+}
+
+impl fmt::Display for AdventureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdventureError::Machine(e) => write!(f, "{}", e),
+            AdventureError::Stalled(c) => write!(f, "game stopped responding after {:?}", c),
+            AdventureError::Lost(c) => write!(f, "no room in the reply to {:?}", c),
+            AdventureError::NoCheckpoint => write!(f, "no security checkpoint found"),
+            AdventureError::TooHeavy => write!(f, "no combination of items passes the checkpoint"),
+        }
+    }
+}
+
+impl From<IntcodeError> for AdventureError {
+    fn from(e: IntcodeError) -> AdventureError {
+        AdventureError::Machine(e)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Room {
+    pub name: String,
+    pub description: String,
+    pub doors: Vec<String>,
+    pub items: Vec<String>,
+}
+
+// Parses the last room described in a reply.  Being thrown out of a room
+// describes two, and it's the second that we end up in.
+fn parse_room(lines: &[String]) -> Option<Room> {
+    let start = lines.iter().rposition(|l| l.starts_with("== "))?;
+    let mut room = Room {
+        name: lines[start].trim_matches(|c| c == '=' || c == ' ').to_string(),
+        ..Room::default()
+    };
+    let mut list: Option<&mut Vec<String>> = None;
+    for line in lines[start + 1..].iter() {
+        match line.as_str() {
+            "Doors here lead:" => list = Some(&mut room.doors),
+            "Items here:" => list = Some(&mut room.items),
+            "" => list = None,
+            "Command?" => break,
+            l => match (l.strip_prefix("- "), list.as_mut()) {
+                (Some(entry), Some(list)) => list.push(entry.to_string()),
+                _ if room.description.is_empty() => room.description = l.to_string(),
+                _ => {},
+            },
+        }
+    }
+    Some(room)
+}
+
+fn opposite(door: &str) -> &str {
+    match door {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        "west" => "east",
+        other => other,
+    }
+}
+
+// Plays a text adventure: explores every room it can reach, picks up what
+// is safe to carry, and finds out which of those items let it through the
+// security checkpoint.  Every command sent to the game is kept, so a session
+// can be saved and replayed.
+pub struct Adventure<C> {
+    console: C,
+    transcript: Vec<String>,
+    here: String,
+    rooms: HashMap<String, Room>,
+    // (room, door) -> room the door leads to
+    map: HashMap<(String, String), String>,
+    inventory: Vec<String>,
+    // the room before the pressure-sensitive floor, and the door onto it
+    checkpoint: Option<(String, String)>,
+}
+
+impl<C: Console> Adventure<C> {
+    pub fn start(mut console: C) -> Result<Adventure<C>, AdventureError> {
+        let lines = console.command("")?
+            .ok_or_else(|| AdventureError::Stalled(String::new()))?
+            .lines;
+        let room = parse_room(&lines).ok_or_else(|| AdventureError::Lost(String::new()))?;
+        let mut rooms = HashMap::new();
+        let here = room.name.to_owned();
+        rooms.insert(room.name.to_owned(), room);
+        Ok(Adventure {
+            console,
+            transcript: Vec::new(),
+            here,
+            rooms,
+            map: HashMap::new(),
+            inventory: Vec::new(),
+            checkpoint: None,
+        })
+    }
+
+    pub fn transcript(&self) -> &[String] {
+        &self.transcript
+    }
+
+    fn send(&mut self, command: &str) -> Result<Response, AdventureError> {
+        self.transcript.push(command.to_string());
+        self.console.command(command)?
+            .ok_or_else(|| AdventureError::Stalled(command.to_string()))
+    }
+
+    // Goes through `door`, returning the room we end up in.
+    fn go(&mut self, door: &str) -> Result<Room, AdventureError> {
+        let res = self.send(door)?;
+        let room = parse_room(&res.lines).ok_or_else(|| AdventureError::Lost(door.to_string()))?;
+        self.map.insert((self.here.to_owned(), door.to_string()), room.name.to_owned());
+        self.rooms.entry(room.name.to_owned()).or_insert_with(|| room.clone());
+        self.here = room.name.to_owned();
+        Ok(room)
+    }
+
+    // Visits every room reachable from here, taking safe items on the way,
+    // and comes back.
+    pub fn explore(&mut self) -> Result<(), AdventureError> {
+        let from = self.here.to_owned();
+        self.collect_items()?;
+        let doors = self.rooms[&from].doors.clone();
+        for door in doors {
+            if self.map.contains_key(&(from.to_owned(), door.to_owned())) {
+                continue;
+            }
+            let seen = self.rooms.len();
+            let room = self.go(&door)?;
+            if room.name == from {
+                // thrown back: the door leads onto the pressure-sensitive floor
+                self.checkpoint = Some((from.to_owned(), door));
+                continue;
+            }
+            let back = opposite(&door).to_string();
+            self.map.insert((room.name.to_owned(), back.to_owned()), from.to_owned());
+            if self.rooms.len() > seen {
+                self.explore()?;
+            }
+            self.go(&back)?;
+        }
+        Ok(())
+    }
+
+    fn collect_items(&mut self) -> Result<(), AdventureError> {
+        let items = self.rooms[&self.here].items.clone();
+        for item in items {
+            if self.inventory.contains(&item) || !self.safe(&item)? {
+                continue;
+            }
+            self.send(&format!("take {}", item))?;
+            self.inventory.push(item);
+        }
+        Ok(())
+    }
+
+    // Tries taking `item` on a copy of the game, and sees whether the game
+    // is still playable afterwards.
+    fn safe(&self, item: &str) -> Result<bool, AdventureError> {
+        let mut trial = self.console.clone();
+        match trial.command(&format!("take {}", item))? {
+            Some(ref res) if !res.halted => {},
+            _ => return Ok(false),
+        }
+        // best to leave by a door known to lead somewhere else
+        let doors = &self.rooms[&self.here].doors;
+        let known = |d: &&String| {
+            let to = self.map.get(&(self.here.to_owned(), d.to_string()));
+            to.is_some_and(|to| *to != self.here)
+        };
+        let checkpoint = self.checkpoint.as_ref().map(|(_, door)| door);
+        let door = doors.iter()
+                        .find(known)
+                        .or_else(|| doors.iter().find(|d| Some(*d) != checkpoint));
+        let door = match door {
+            Some(door) => door,
+            None => return Ok(true),
+        };
+        match trial.command(door)? {
+            Some(ref res) if !res.halted => Ok(parse_room(&res.lines).is_some()),
+            _ => Ok(false),
+        }
+    }
+
+    // the doors to go through to get from here to `to`
+    fn route(&self, to: &str) -> Option<Vec<String>> {
+        let mut came_by: HashMap<&str, (&str, &str)> = HashMap::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut queue = VecDeque::new();
+        seen.insert(&self.here);
+        queue.push_back(self.here.as_str());
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                let mut doors = Vec::new();
+                let mut at = room;
+                while let Some((prev, door)) = came_by.get(at) {
+                    doors.push(door.to_string());
+                    at = prev;
+                }
+                doors.reverse();
+                return Some(doors);
+            }
+            for ((from, door), next) in self.map.iter() {
+                if from == room && seen.insert(next) {
+                    came_by.insert(next, (from, door));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    // Goes to the checkpoint and tries each set of items carried until the
+    // floor lets us through, returning what the game says then.
+    pub fn crack(&mut self) -> Result<Vec<String>, AdventureError> {
+        let (room, door) = self.checkpoint.to_owned().ok_or(AdventureError::NoCheckpoint)?;
+        for d in self.route(&room).ok_or(AdventureError::NoCheckpoint)? {
+            self.go(&d)?;
+        }
+
+        // work through the subsets in Gray code order, so that only one item
+        // changes hands between one try and the next
+        let items = self.inventory.to_owned();
+        let mut held = (1u64 << items.len()) - 1;
+        for i in 0..(1u64 << items.len()) {
+            let want = i ^ (i >> 1);
+            for (bit, item) in items.iter().enumerate() {
+                let mask = 1 << bit;
+                if want & mask != held & mask {
+                    let verb = if want & mask != 0 { "take" } else { "drop" };
+                    self.send(&format!("{} {}", verb, item))?;
+                }
+            }
+            held = want;
+            let res = self.send(&door)?;
+            if res.halted {
+                return Ok(res.lines);
+            }
+        }
+        Err(AdventureError::TooHeavy)
+    }
+}
+
+// Sends every command of a transcript to the game, returning everything the
+// game printed along the way.
+pub fn replay<C: Console>(mut console: C, transcript: &[String]) -> Result<Vec<String>, AdventureError> {
+    let mut lines = Vec::new();
+    for command in [String::new()].iter().chain(transcript.iter()) {
+        let res = console.command(command)?
+            .ok_or_else(|| AdventureError::Stalled(command.to_string()))?;
+        lines.extend(res.lines);
+        if res.halted {
+            break;
+        }
+    }
+    Ok(lines)
+}
+
+// the first number in the game's parting words
+fn password(lines: &[String]) -> Option<i64> {
+    lines.iter()
+         .flat_map(|l| l.split(|c: char| !c.is_ascii_digit()))
+         .find(|w| !w.is_empty())
+         .and_then(|w| w.parse().ok())
+}
+
+// Solves the game, returning the password and the commands which found it.
+pub fn play(program: Vec<i64>) -> Result<(i64, Vec<String>), AdventureError> {
+    let mut game = Adventure::start(AsciiMachine::new(program))?;
+    game.explore()?;
+    let lines = game.crack()?;
+    let password = password(&lines).ok_or_else(|| AdventureError::Lost("password".to_string()))?;
+    Ok((password, game.transcript().to_vec()))
+}
+
+// Solves the game, saving the winning commands to `save` if given.
+pub fn twentyfive_a<I>(buf: I, save: Option<&String>) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let (password, transcript) = match play(p) {
+        Ok(solved) => solved,
+        Err(e) => panic!("{}", e),
+    };
+    if let Some(path) = save {
+        fs::write(path, transcript.join("\n") + "\n").expect("failed to save transcript");
+    }
+    password
+}
+
+// Plays back a saved transcript, printing the game as it goes.
+pub fn twentyfive_replay<I>(buf: I, path: &str)
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let text = fs::read_to_string(path).expect("failed to read transcript");
+    let transcript: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+    match replay(AsciiMachine::new(p), &transcript) {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tiny ship to play on, laid out as
+    //
+    //   Kitchen         Pressure-Sensitive Floor
+    //      |                     |
+    //   Hull Breach --- Security Checkpoint
+    //
+    // The kitchen has an item which locks up the game when taken, and the
+    // checkpoint one which stops you moving.  Only the cake on its own
+    // weighs the right amount.
+    #[derive(Clone)]
+    struct Ship {
+        room: &'static str,
+        items: HashMap<&'static str, Vec<&'static str>>,
+        held: Vec<String>,
+        stuck: bool,
+    }
+
+    impl Ship {
+        fn new() -> Ship {
+            let mut items = HashMap::new();
+            items.insert("Hull Breach", vec!["mug"]);
+            items.insert("Kitchen", vec!["infinite loop", "cake"]);
+            items.insert("Security Checkpoint", vec!["giant electromagnet"]);
+            Ship { room: "Hull Breach", items, held: Vec::new(), stuck: false }
+        }
+
+        fn doors(room: &str) -> Vec<(&'static str, &'static str)> {
+            match room {
+                "Hull Breach" => vec![("north", "Kitchen"), ("east", "Security Checkpoint")],
+                "Kitchen" => vec![("south", "Hull Breach")],
+                "Security Checkpoint" => vec![("north", "Pressure-Sensitive Floor"), ("west", "Hull Breach")],
+                _ => vec![],
+            }
+        }
+
+        fn describe(&self, room: &str, lines: &mut Vec<String>) {
+            lines.push(String::new());
+            lines.push(format!("== {} ==", room));
+            lines.push(format!("This is the {}.", room.to_lowercase()));
+            lines.push(String::new());
+            lines.push("Doors here lead:".to_string());
+            for (door, _) in Ship::doors(room) {
+                lines.push(format!("- {}", door));
+            }
+            let items = &self.items[room];
+            if !items.is_empty() {
+                lines.push(String::new());
+                lines.push("Items here:".to_string());
+                for item in items {
+                    lines.push(format!("- {}", item));
+                }
+            }
+        }
+    }
+
+    impl Console for Ship {
+        fn command(&mut self, command: &str) -> Result<Option<Response>, IntcodeError> {
+            let mut res = Response::default();
+            let lines = &mut res.lines;
+            let room = self.room;
+            match command.split_at(command.find(' ').unwrap_or(command.len())) {
+                ("", _) => self.describe(room, lines),
+                ("take", item) => {
+                    let item = item.trim();
+                    if item == "infinite loop" {
+                        return Ok(None);
+                    }
+                    self.stuck |= item == "giant electromagnet";
+                    self.items.get_mut(room).unwrap().retain(|i| *i != item);
+                    self.held.push(item.to_string());
+                    lines.push(format!("You take the {}.", item));
+                },
+                ("drop", item) => {
+                    let item = item.trim();
+                    self.held.retain(|i| i != item);
+                    lines.push(format!("You drop the {}.", item));
+                },
+                (door, _) => {
+                    let next = Ship::doors(room).into_iter().find(|(d, _)| *d == door);
+                    match next {
+                        _ if self.stuck => lines.push("You can't move!!".to_string()),
+                        Some((_, "Pressure-Sensitive Floor")) => {
+                            lines.push("== Pressure-Sensitive Floor ==".to_string());
+                            let mut held = self.held.to_owned();
+                            held.sort();
+                            if held == ["cake"] {
+                                lines.push("Santa says: type 2468 on the keypad".to_string());
+                                res.halted = true;
+                                return Ok(Some(res));
+                            }
+                            lines.push("Alert! You are ejected back to the checkpoint.".to_string());
+                            self.describe(room, lines);
+                        },
+                        Some((_, next)) => {
+                            self.room = next;
+                            self.describe(next, lines);
+                        },
+                        None => lines.push("You can't go that way.".to_string()),
+                    }
+                },
+            }
+            res.lines.push(String::new());
+            res.lines.push("Command?".to_string());
+            Ok(Some(res))
+        }
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_room() {
+        let reply = lines("
+
+== Pressure-Sensitive Floor ==
+Analyzing...
+
+Doors here lead:
+- south
+
+A loud, robotic voice says \"Alert!\" and you are ejected back to the checkpoint.
+
+
+
+== Security Checkpoint ==
+In the next room, a pressure-sensitive floor will verify your identity.
+
+Doors here lead:
+- north
+- west
+
+Items here:
+- space heater
+- mug
+
+Command?");
+        assert_eq!(Some(Room {
+            name: "Security Checkpoint".to_string(),
+            description: "In the next room, a pressure-sensitive floor will verify your identity.".to_string(),
+            doors: vec!["north".to_string(), "west".to_string()],
+            items: vec!["space heater".to_string(), "mug".to_string()],
+        }), parse_room(&reply));
+        assert_eq!(None, parse_room(&lines("You can't go that way.\n\nCommand?")));
+    }
+
+    #[test]
+    fn test_play() {
+        let mut game = Adventure::start(Ship::new()).unwrap();
+        game.explore().unwrap();
+        let mut carried = game.inventory.to_owned();
+        carried.sort();
+        assert_eq!(vec!["cake", "mug"], carried);
+        assert_eq!(Some(("Security Checkpoint".to_string(), "north".to_string())), game.checkpoint);
+
+        let lines = game.crack().unwrap();
+        assert_eq!(Some(2468), password(&lines));
+
+        let transcript = game.transcript().to_vec();
+        assert_eq!(Some(2468), password(&replay(Ship::new(), &transcript).unwrap()));
+    }
+}
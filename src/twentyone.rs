@@ -0,0 +1,377 @@
+use std::fmt;
+use std::io::BufRead;
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::intcode;
+use crate::intcode::{AsciiMachine, IntcodeError};
+
+// most instructions a springdroid will take, not counting WALK or RUN
+const MAX_INSTRUCTIONS: usize = 15;
+
+// A jump condition over the droid's sensors, as written, e.g. `!(A & B) & D`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Sensor(char),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // the droid can see four tiles ahead, A to D
+    Walk,
+    // the droid can see nine tiles ahead, A to I
+    Run,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ScriptError {
+    // the expression couldn't be parsed at the given character offset
+    Parse(usize, String),
+    // a sensor the droid doesn't have in this mode
+    Sensor(char),
+    // T and J between them can't hold everything the expression needs
+    TooComplex,
+    // the script compiled to this many instructions
+    TooLong(usize),
+    Machine(IntcodeError),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Parse(at, message) => write!(f, "at {}: {}", at, message),
+            ScriptError::Sensor(s) => write!(f, "no sensor {} in this mode", s),
+            ScriptError::TooComplex => write!(f, "expression needs more than two registers"),
+            ScriptError::TooLong(n) => {
+                write!(f, "{} instructions, but the droid takes at most {}", n, MAX_INSTRUCTIONS)
+            },
+            ScriptError::Machine(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<IntcodeError> for ScriptError {
+    fn from(e: IntcodeError) -> ScriptError {
+        ScriptError::Machine(e)
+    }
+}
+
+// Parses an expression of sensors A to I, `!`, `&`, `|` and parentheses.
+// `&` binds tighter than `|`.
+pub fn parse(text: &str) -> Result<Expr, ScriptError> {
+    let mut parser = Parser { chars: text.chars().peekable(), at: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(c) => Err(ScriptError::Parse(parser.at, format!("unexpected {:?}", c))),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+            self.at += 1;
+        }
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.chars.next();
+        self.at += 1;
+        c
+    }
+
+    // the offset of the next character, after any whitespace
+    fn position(&mut self) -> usize {
+        self.peek();
+        self.at
+    }
+
+    fn or(&mut self) -> Result<Expr, ScriptError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some('|') {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ScriptError> {
+        let mut expr = self.not()?;
+        while self.peek() == Some('&') {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, ScriptError> {
+        let at = self.position();
+        match self.next() {
+            Some('!') => Ok(Expr::Not(Box::new(self.not()?))),
+            Some('(') => {
+                let expr = self.or()?;
+                let at = self.position();
+                match self.next() {
+                    Some(')') => Ok(expr),
+                    _ => Err(ScriptError::Parse(at, "expected )".to_string())),
+                }
+            },
+            Some(c @ 'A'..='I') => Ok(Expr::Sensor(c)),
+            Some(c) => Err(ScriptError::Parse(at, format!("unexpected {:?}", c))),
+            None => Err(ScriptError::Parse(at, "unexpected end".to_string())),
+        }
+    }
+}
+
+// Compiles `expr` into a springscript program which jumps when it is true,
+// ending with WALK or RUN as `mode` says.
+pub fn compile(expr: &Expr, mode: Mode) -> Result<String, ScriptError> {
+    check_sensors(expr, mode)?;
+    let mut code = Vec::new();
+    if !emit(expr, 'J', Some('T'), &mut code) {
+        return Err(ScriptError::TooComplex);
+    }
+    if code.len() > MAX_INSTRUCTIONS {
+        return Err(ScriptError::TooLong(code.len()));
+    }
+    code.push(match mode {
+        Mode::Walk => "WALK".to_string(),
+        Mode::Run => "RUN".to_string(),
+    });
+    Ok(code.join("\n") + "\n")
+}
+
+fn check_sensors(expr: &Expr, mode: Mode) -> Result<(), ScriptError> {
+    match expr {
+        Expr::Sensor(s) if mode == Mode::Walk && *s > 'D' => Err(ScriptError::Sensor(*s)),
+        Expr::Sensor(_) => Ok(()),
+        Expr::Not(e) => check_sensors(e, mode),
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            check_sensors(a, mode)?;
+            check_sensors(b, mode)
+        },
+    }
+}
+
+// Appends instructions leaving the value of `expr` in `dst`, using `scratch`
+// if it must.  Returns false if that can't be done without more registers.
+fn emit(expr: &Expr, dst: char, scratch: Option<char>, code: &mut Vec<String>) -> bool {
+    match expr {
+        Expr::Sensor(s) => {
+            code.push(format!("NOT {} {}", s, dst));
+            code.push(format!("NOT {} {}", dst, dst));
+            true
+        },
+        Expr::Not(e) => match **e {
+            Expr::Sensor(s) => {
+                code.push(format!("NOT {} {}", s, dst));
+                true
+            },
+            _ => {
+                let ok = emit(e, dst, scratch, code);
+                code.push(format!("NOT {} {}", dst, dst));
+                ok
+            },
+        },
+        Expr::And(a, b) => emit_binary("AND", a, b, dst, scratch, code),
+        Expr::Or(a, b) => emit_binary("OR", a, b, dst, scratch, code),
+    }
+}
+
+fn emit_binary(op: &str, a: &Expr, b: &Expr, dst: char, scratch: Option<char>,
+               code: &mut Vec<String>) -> bool {
+    // with a bare sensor on either side, no second register is needed
+    match (a, b) {
+        (_, Expr::Sensor(s)) | (Expr::Sensor(s), _) => {
+            let other = if let Expr::Sensor(_) = b { a } else { b };
+            let ok = emit(other, dst, scratch, code);
+            code.push(format!("{} {} {}", op, s, dst));
+            return ok;
+        },
+        _ => {},
+    }
+    let scratch = match scratch {
+        Some(s) => s,
+        None => return false,
+    };
+    // one side goes in scratch, where dst is free to use, and then the other
+    // has to fit in dst alone
+    for (first, second) in [(a, b), (b, a)].iter() {
+        let mut attempt = Vec::new();
+        if emit(first, scratch, Some(dst), &mut attempt) && emit(second, dst, None, &mut attempt) {
+            code.extend(attempt);
+            code.push(format!("{} {} {}", op, scratch, dst));
+            return true;
+        }
+    }
+    false
+}
+
+// How a springdroid's run ended
+#[derive(Debug, PartialEq)]
+pub enum Droid {
+    // made it, reporting the hull damage found
+    Survived(i64),
+    // fell into a hole; the last frame of the animation showing where
+    Fell(Vec<String>),
+}
+
+// Loads `script` into the springdroid program and sends it off.
+pub fn run_droid(program: Vec<i64>, script: &str) -> Result<Droid, ScriptError> {
+    let mut mach = AsciiMachine::new(program);
+    mach.run()?;
+    mach.send(script);
+    let res = mach.run()?;
+    match res.values.first() {
+        Some(damage) => Ok(Droid::Survived(*damage)),
+        None => Ok(Droid::Fell(last_frame(&res.lines))),
+    }
+}
+
+// the last block of lines that shows some hull
+fn last_frame(lines: &[String]) -> Vec<String> {
+    let mut blocks = lines.split(|l| l.is_empty());
+    let frame = blocks.rfind(|b| b.iter().any(|l| l.contains('#')));
+    frame.unwrap_or(&[]).to_vec()
+}
+
+fn survey<I>(buf: I, condition: &str, mode: Mode) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let script = compile(&parse(condition).unwrap(), mode).unwrap();
+    match run_droid(p, &script) {
+        Ok(Droid::Survived(damage)) => damage,
+        Ok(Droid::Fell(frame)) => panic!("droid fell:\n{}", frame.join("\n")),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+pub fn twentyone_a<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    // jump over any hole ahead, as long as there's somewhere to land
+    survey(buf, "!(A & B & C) & D", Mode::Walk)
+}
+
+pub fn twentyone_b<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    // as before, but only if we can go on from where we land, by stepping
+    // forward or jumping again at once
+    survey(buf, "!(A & B & C) & D & (E | H)", Mode::Run)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs a springscript program on the given sensor readings, A first
+    fn jumps(script: &str, sensors: u32) -> bool {
+        let (mut t, mut j) = (false, false);
+        for line in script.lines() {
+            let words: Vec<&str> = line.split(' ').collect();
+            if words.len() != 3 {
+                continue;
+            }
+            let x = match words[1] {
+                "T" => t,
+                "J" => j,
+                s => sensors & (1 << (s.as_bytes()[0] - b'A')) != 0,
+            };
+            let y = if words[2] == "T" { &mut t } else { &mut j };
+            *y = match words[0] {
+                "AND" => x && *y,
+                "OR" => x || *y,
+                _ => !x,
+            };
+        }
+        j
+    }
+
+    fn eval(expr: &Expr, sensors: u32) -> bool {
+        match expr {
+            Expr::Sensor(s) => sensors & (1 << (*s as u8 - b'A')) != 0,
+            Expr::Not(e) => !eval(e, sensors),
+            Expr::And(a, b) => eval(a, sensors) && eval(b, sensors),
+            Expr::Or(a, b) => eval(a, sensors) || eval(b, sensors),
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let script = compile(&parse("!(A & B & C) & D").unwrap(), Mode::Walk).unwrap();
+        assert_eq!("\
+NOT A J
+NOT J J
+AND B J
+AND C J
+NOT J J
+AND D J
+WALK
+", script);
+    }
+
+    #[test]
+    fn test_compiled_scripts_agree() {
+        for text in ["!(A & B & C) & D & (E | H)", "(A | B) & (C | D)", "!(!A | B & !C)",
+                     "(A & B | C) & !(D | E)", "!E"].iter() {
+            let expr = parse(text).unwrap();
+            let script = compile(&expr, Mode::Run).unwrap();
+            for sensors in 0..1 << 9 {
+                assert_eq!(eval(&expr, sensors), jumps(&script, sensors), "{} on {:09b}", text, sensors);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Err(ScriptError::Parse(4, "unexpected 'X'".to_string())), parse("A & X"));
+        assert_eq!(Err(ScriptError::Parse(6, "expected )".to_string())), parse("(A | B"));
+        assert_eq!(Err(ScriptError::Parse(2, "unexpected 'B'".to_string())), parse("A B"));
+        assert_eq!(Err(ScriptError::Parse(3, "unexpected end".to_string())), parse("A |"));
+        assert_eq!(Err(ScriptError::Sensor('E')), compile(&parse("A & E").unwrap(), Mode::Walk));
+        assert_eq!(Err(ScriptError::TooComplex),
+                   compile(&parse("(A | B) & (C | D) | (E | F) & (G | H)").unwrap(), Mode::Run));
+        assert_eq!(Err(ScriptError::TooLong(16)),
+                   compile(&parse("!!!!!!!!!!!!!!!!A").unwrap(), Mode::Walk));
+    }
+
+    #[test]
+    fn test_last_frame() {
+        let out: Vec<String> = "\
+Walking...
+
+
+Didn't make it across:
+
+.................
+.................
+@................
+#####.#..########
+
+.................
+.................
+.................
+#####@#..########
+".lines().map(|l| l.to_string()).collect();
+        assert_eq!(vec![".................",
+                        ".................",
+                        ".................",
+                        "#####@#..########"], last_frame(&out));
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::BufRead;
+use crate::intcode;
+use crate::intcode::{NatEvent, PacketNetwork};
+
+const NICS: usize = 50;
+
+pub fn twentythree_a<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let mut net = PacketNetwork::new(p, NICS);
+    loop {
+        match net.next_event().unwrap() {
+            NatEvent::Received(packet) => return packet.y,
+            NatEvent::Deadlock(waiting) => panic!("NICs {:?} deadlocked", waiting),
+            NatEvent::Wake(_) => {},
+        }
+    }
+}
+
+pub fn twentythree_b<I>(buf: I) -> i64
+where
+    I: BufRead,
+{
+    let p = intcode::read_program(buf).unwrap();
+    let mut net = PacketNetwork::new(p, NICS);
+    let mut last_y = None;
+    loop {
+        match net.next_event().unwrap() {
+            NatEvent::Wake(packet) => {
+                if last_y == Some(packet.y) {
+                    return packet.y;
+                }
+                last_y = Some(packet.y);
+            },
+            NatEvent::Deadlock(waiting) => panic!("NICs {:?} deadlocked", waiting),
+            NatEvent::Received(_) => {},
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::BufRead;
 use crate::intcode;
-use crate::intcode::IntcodeMachine;
+use std::thread;
+use crate::intcode::{IntcodeMachine, Search, Slot};
+
+const NEEDLE: i64 = 19690720;
 
 
 pub fn two_a<I>(buf: I) -> i64
@@ -8,39 +11,34 @@ where
     I: BufRead,
 {
 
-    let mut p = intcode::read_program(buf);
+    let mut p = intcode::read_program(buf).unwrap();
     p[1] = 12;
     p[2] = 2;
 
     let (_m_in, _m_out, mut mach) = IntcodeMachine::new(p);
-    mach.run_program()
+    mach.run_program().unwrap()
 }
 
-pub fn two_b<I>(buf: I) -> i64
+// Finds the noun and verb which make the program leave `NEEDLE` in cell 0,
+// as 100 * noun + verb.  The program is straight-line code which is linear
+// in the noun and verb, so that can usually be solved for directly; if not,
+// every pair is tried.
+pub fn two_b<I>(buf: I) -> Option<i64>
 where
     I: BufRead,
 {
 
-    let p = intcode::read_program(buf);
-    let mut noun: i64 = 0;
-    let mut out: i64;
-    let needle: i64 = 19690720;
-    while noun < 100 {
-        let mut verb: i64 = 0;
-        while verb < 100 {
-            let mut q = p.to_owned();
-            q[1] = noun;
-            q[2] = verb;
-            let (_m_in, _m_out, mut mach) = IntcodeMachine::new(q);
-            out = mach.run_program();
-
-            if needle == out {
-                return (100 * noun) + verb;
-            }
-            verb += 1;
-        }
-        noun += 1;
+    let p = intcode::read_program(buf).unwrap();
+    let ranges = [(1, 0..=99), (2, 0..=99)];
+    if let Ok(solutions) = intcode::exit_value(&p, &[1, 2]).and_then(|v| v.solve(NEEDLE, &ranges)) {
+        return solutions.first().map(|s| 100 * s[0] + s[1]);
     }
 
-    panic!("no input yields value 19690720");
+    let mut search = Search::new(p);
+    for (cell, range) in ranges.iter() {
+        search.vary(Slot::Cell(*cell), range.to_owned()).unwrap();
+    }
+    search.parallel(thread::available_parallelism().map_or(1, |n| n.get()));
+    let found = search.first(|run| run.exit == NEEDLE)?;
+    Some(100 * found.values[0] + found.values[1])
 }